@@ -0,0 +1,68 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use language_model::{LMState, LanguageModel, LanguageModelBuilder};
+
+// Number of words of the vocabulary and of the generated corpus
+const NO_SYMBOLS: u64 = 50_000;
+const NO_WORDS: usize = 1_000_000;
+const SENTENCE_LEN: usize = 12;
+
+/// Build a trigram model from a generated corpus whose word frequencies roughly follow Zipf's
+/// law, so the vocabulary is large and most words are rare
+fn build_model() -> LanguageModel {
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next_word = || {
+        // A xorshift generator keeps the corpus the same between runs
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        let uniform = (seed >> 11) as f64 / (1u64 << 53) as f64;
+        let rank = (NO_SYMBOLS as f64).powf(uniform) as u64;
+        format!("w{}", rank)
+    };
+    let mut builder = LanguageModelBuilder::new();
+    for _ in 0..NO_WORDS / SENTENCE_LEN {
+        let sentence: Vec<String> = (0..SENTENCE_LEN).map(|_| next_word()).collect();
+        let words: Vec<&str> = sentence.iter().map(String::as_str).collect();
+        builder.add_sentence(&words);
+    }
+    builder
+        .build()
+        .expect("The generated corpus is a valid model")
+}
+
+fn predict(c: &mut Criterion) {
+    let language_model = build_model();
+    let context = language_model.get_next_state(LMState::default(), "w1");
+
+    // Collecting every word is what predicting from the initial state used to cost for any
+    // number of predictions
+    let mut group = c.benchmark_group("predict");
+    for max_no_predictions in [10, NO_SYMBOLS as usize] {
+        group.bench_with_input(
+            BenchmarkId::new("initial_state", max_no_predictions),
+            &max_no_predictions,
+            |b, &max_no_predictions| {
+                b.iter(|| language_model.predict(black_box(LMState::default()), max_no_predictions))
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("context", max_no_predictions),
+            &max_no_predictions,
+            |b, &max_no_predictions| {
+                b.iter(|| language_model.predict(black_box(context), max_no_predictions))
+            },
+        );
+    }
+    group.bench_function("prefix", |b| {
+        b.iter(|| language_model.predict_with_prefix(black_box(context), "w12", 10))
+    });
+    // Reusing the buffer avoids the allocations of predict
+    let mut predictions = Vec::new();
+    group.bench_function("labels", |b| {
+        b.iter(|| language_model.predict_labels(black_box(context), 10, &mut predictions))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, predict);
+criterion_main!(benches);
@@ -0,0 +1,134 @@
+use std::{
+    f32::consts::LN_10,
+    fs::File,
+    io::{BufWriter, Read, Write},
+};
+
+use super::{builder::NgramEntry, *};
+
+impl LanguageModel {
+    /// Read the language model from a file in the ARPA format
+    ///
+    /// The log10 probabilities and backoff weights of the file are converted to natural
+    /// logarithms. The order of the model is the highest order of the file. The model uses Katz
+    /// backoff with the backoff weights of the file
+    pub fn read_arpa(fname: &str) -> Result<Self, Error> {
+        Self::from_arpa_ngrams(ArpaIterator::new(fname)?)
+    }
+
+    /// Read the language model from a reader providing a file in the ARPA format
+    pub fn read_arpa_from<R: Read>(reader: R) -> Result<Self, Error> {
+        Self::from_arpa_ngrams(ArpaIterator::from_reader(reader, "reader"))
+    }
+
+    fn from_arpa_ngrams(mut arpa_ngrams: ArpaIterator<impl Read>) -> Result<Self, Error> {
+        let mut symt = IndexSet::new();
+        let mut ngrams: Vec<Vec<NgramEntry>> = Vec::new();
+
+        for ngram in arpa_ngrams.by_ref() {
+            let (words, log_prob, backoff) = ngram?;
+            let log_prob = log_prob * LN_10;
+            let backoff = backoff.map(|backoff| backoff * LN_10);
+            if words.len() == 1 {
+                let (_, is_new) = symt.insert_full(words[0].clone());
+                if !is_new {
+                    let message = format!("The unigram {} is listed twice", words[0]);
+                    return Err(Error::Structure(message));
+                }
+            }
+            let labels = words
+                .iter()
+                .map(|word| label_of(&symt, word))
+                .collect::<Result<Vec<Label>, Error>>()?;
+            if ngrams.len() < labels.len() {
+                ngrams.resize_with(labels.len(), Vec::new);
+            }
+            ngrams[labels.len() - 1].push((labels, log_prob, backoff));
+        }
+        symt.shrink_to_fit();
+
+        // A file whose sections hold another number of n-grams than its header declares was
+        // most likely truncated. Files without counts in the header are not checked
+        let declared_counts = arpa_ngrams.declared_counts();
+        let no_checked_orders = if declared_counts.is_empty() {
+            0
+        } else {
+            declared_counts.len().max(ngrams.len())
+        };
+        for order in 1..=no_checked_orders {
+            let declared = declared_counts.get(order - 1).copied().unwrap_or(0);
+            let found = ngrams.get(order - 1).map_or(0, Vec::len);
+            if declared != found {
+                let message = format!(
+                    "The header declares {} {}-grams, but the file contains {}",
+                    declared, order, found
+                );
+                return Err(Error::Structure(message));
+            }
+        }
+
+        Self::from_ngrams(symt, ngrams, Smoothing::Katz)
+    }
+
+    /// Write the language model to a file in the ARPA format
+    ///
+    /// The probabilities and backoff weights are converted to log10. Backoff weights are only
+    /// written if the model has them
+    pub fn write_arpa(&self, fname: &str) -> Result<(), Error> {
+        self.write_arpa_to(BufWriter::new(File::create(fname)?))
+    }
+
+    /// Write the language model in the ARPA format to a writer
+    pub fn write_arpa_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let ngrams = self.to_ngrams();
+
+        // The header lists the number of n-grams of each order
+        writeln!(writer)?;
+        writeln!(writer, "\\data\\")?;
+        for (level, entries) in ngrams.iter().enumerate() {
+            writeln!(writer, "ngram {}={}", level + 1, entries.len())?;
+        }
+
+        for (level, entries) in ngrams.into_iter().enumerate() {
+            writeln!(writer)?;
+            writeln!(writer, "\\{}-grams:", level + 1)?;
+            for (labels, log_prob, backoff) in entries {
+                let words: Vec<&str> = labels
+                    .iter()
+                    .map(|&label| &self.symt[label as usize][..])
+                    .collect();
+                write_arpa_line(&mut writer, log_prob, &words, backoff)?;
+            }
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "\\end\\")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Write the line of an n-gram with its probability and backoff weight converted to log10
+fn write_arpa_line<W: Write>(
+    writer: &mut W,
+    log_prob: LogProb,
+    words: &[&str],
+    backoff: Option<LogProb>,
+) -> std::io::Result<()> {
+    write!(writer, "{}\t{}", log_prob / LN_10, words.join(" "))?;
+    if let Some(backoff) = backoff {
+        write!(writer, "\t{}", backoff / LN_10)?;
+    }
+    writeln!(writer)
+}
+
+/// Look up the label of a word of an n-gram. All words have to be listed as unigrams
+fn label_of(symt: &IndexSet<Symbol>, symbol: &str) -> Result<Label, Error> {
+    match symt.get_index_of(symbol) {
+        Some(label) => Ok(label as Label),
+        None => Err(Error::Structure(format!(
+            "The word {} is not listed as a unigram",
+            symbol
+        ))),
+    }
+}
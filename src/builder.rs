@@ -0,0 +1,607 @@
+use std::collections::HashMap;
+
+use super::*;
+
+// Discount subtracted from the count of each n-gram above unigrams when training for Katz backoff
+// It is also used by Kneser-Ney smoothing if there are too few n-grams to estimate the discounts
+const KATZ_DISCOUNT: f64 = 0.5;
+
+// Order of the models built by a builder created with `new`
+const DEFAULT_ORDER: usize = 3;
+
+/// Collects the n-gram counts of a text corpus and builds a language model from them
+///
+/// Every line of the corpus is treated as a sentence and split into words at whitespace.
+/// N-grams never span two sentences.
+#[derive(Debug)]
+pub struct LanguageModelBuilder {
+    symt: IndexSet<Symbol>,
+    // The counts of the n-grams of each order given by the labels of their words, starting
+    // with the unigrams
+    counts: Vec<HashMap<Vec<Label>, u64>>,
+    smoothing: Smoothing,
+    // N-grams above unigrams counted less often are left out of the built model
+    min_count: u64,
+    // Every sentence is enclosed in the sentence start and end symbols
+    sentence_boundaries: bool,
+}
+
+impl Default for LanguageModelBuilder {
+    fn default() -> Self {
+        Self::with_order(DEFAULT_ORDER)
+    }
+}
+
+impl LanguageModelBuilder {
+    /// Create a builder for a trigram model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a builder for a model whose longest n-grams have the given number of words
+    pub fn with_order(order: usize) -> Self {
+        Self {
+            symt: IndexSet::new(),
+            counts: vec![HashMap::new(); order],
+            smoothing: Smoothing::default(),
+            min_count: 1,
+            sentence_boundaries: false,
+        }
+    }
+
+    /// Add a text to the corpus. Each line of the text is a sentence
+    pub fn add_text(&mut self, text: &str) -> &mut Self {
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            self.add_sentence(&words);
+        }
+        self
+    }
+
+    /// Add a single tokenized sentence to the corpus
+    pub fn add_sentence(&mut self, words: &[&str]) -> &mut Self {
+        let mut labels: Vec<Label> = words.iter().map(|word| self.insert_symbol(word)).collect();
+        if self.sentence_boundaries && !labels.is_empty() {
+            labels.insert(0, self.insert_symbol(SENTENCE_START));
+            labels.push(self.insert_symbol(SENTENCE_END));
+        }
+
+        for (level, counts) in self.counts.iter_mut().enumerate() {
+            for window in labels.windows(level + 1) {
+                *counts.entry(window.to_vec()).or_insert(0) += 1;
+            }
+        }
+        self
+    }
+
+    /// Add the count of an n-gram given by its words
+    ///
+    /// This allows building a model from counts collected elsewhere instead of a corpus. The
+    /// counts of all orders have to be added as if they were counted in a corpus, so every
+    /// prefix and suffix of an n-gram needs a count as well. N-grams that are empty or longer
+    /// than the order of the model and zero counts are ignored
+    pub fn add_count(&mut self, words: &[&str], count: u64) -> &mut Self {
+        if words.is_empty() || words.len() > self.counts.len() || count == 0 {
+            return self;
+        }
+        let labels: Vec<Label> = words.iter().map(|word| self.insert_symbol(word)).collect();
+        *self.counts[labels.len() - 1].entry(labels).or_insert(0) += count;
+        self
+    }
+
+    /// Set the way backoffs are penalized by the built model
+    pub fn smoothing(&mut self, smoothing: Smoothing) -> &mut Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Leave out the n-grams above unigrams that were counted less often than the minimum count
+    ///
+    /// Their probability mass is given to the backoff weights of their contexts, so the built
+    /// model is smaller. Every unigram is kept
+    pub fn min_count(&mut self, min_count: u64) -> &mut Self {
+        self.min_count = min_count;
+        self
+    }
+
+    /// Enclose every sentence added afterwards in the symbols `<s>` and `</s>`
+    ///
+    /// The built model can then predict the words starting a sentence from
+    /// `LMState::sentence_start` and score the end of a sentence with
+    /// `LanguageModel::score_sentence`. Both symbols are counted like words, so they get
+    /// unigram probabilities as well. Empty sentences are not enclosed
+    pub fn sentence_boundaries(&mut self, sentence_boundaries: bool) -> &mut Self {
+        self.sentence_boundaries = sentence_boundaries;
+        self
+    }
+
+    /// Estimate the probabilities of the counted n-grams and lay them out as a language model
+    ///
+    /// With stupid backoff the probabilities are maximum likelihood estimates. The probability
+    /// of an n-gram is its count divided by the number of times its context was followed by any
+    /// word. With Katz backoff a constant discount is subtracted from the count of each n-gram
+    /// above unigrams and the backoff weights distribute the freed probability mass over the
+    /// words never seen in the context. With Kneser-Ney smoothing the probabilities are
+    /// estimated with interpolated modified Kneser-Ney smoothing
+    pub fn build(&self) -> Result<LanguageModel, Error> {
+        let order = self.counts.len();
+        if order == 0 {
+            return Err(Error::Structure(
+                "The order of a language model has to be at least one".to_string(),
+            ));
+        }
+        let ngrams = match self.smoothing {
+            Smoothing::StupidBackoff => self.backoff_ngrams(0.0)?,
+            Smoothing::Katz => self.backoff_ngrams(KATZ_DISCOUNT)?,
+            Smoothing::KneserNey => self.kneser_ney_ngrams()?,
+        };
+        LanguageModel::from_ngrams(self.symt.clone(), ngrams, self.smoothing)
+    }
+
+    /// Estimate the probabilities by subtracting a constant discount from the counts of the
+    /// n-grams above unigrams and the backoff weights of Katz backoff if it is used
+    fn backoff_ngrams(&self, discount: f64) -> Result<Vec<Vec<NgramEntry>>, Error> {
+        let order = self.counts.len();
+
+        // Count how often each context was followed by another word and how much of the count
+        // is freed by discounting. The whole count of a left out n-gram is freed. The context of
+        // the unigrams is empty
+        let histories: Vec<HashMap<&[Label], (u64, f64)>> = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(level, counts)| {
+                let discount = if level == 0 { 0.0 } else { discount };
+                let mut histories = HashMap::new();
+                for (ngram, &count) in counts {
+                    let history = histories.entry(&ngram[..level]).or_insert((0, 0.0));
+                    history.0 += count;
+                    history.1 += if self.is_left_out(ngram, count) {
+                        count as f64
+                    } else {
+                        discount
+                    };
+                }
+                histories
+            })
+            .collect();
+
+        let probs: Vec<HashMap<&[Label], f64>> = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(level, counts)| {
+                let discount = if level == 0 { 0.0 } else { discount };
+                counts
+                    .iter()
+                    .filter(|(ngram, &count)| !self.is_left_out(ngram, count))
+                    .map(|(ngram, &count)| {
+                        let history_count = histories[level][&ngram[..level]].0;
+                        (&ngram[..], (count as f64 - discount) / history_count as f64)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // The backoff weight of a context is the freed probability mass divided by the mass
+        // the shorter context assigns to the words never seen in the context
+        let mut backoffs: Vec<HashMap<&[Label], LogProb>> = vec![HashMap::new(); order];
+        if self.smoothing == Smoothing::Katz {
+            // The distribution of a context does not sum up to one if its successors cover the
+            // whole vocabulary, which has to be considered by its longer contexts
+            let mut masses: Vec<HashMap<&[Label], f64>> = vec![HashMap::new(); order];
+            for level in 0..order - 1 {
+                let mut covered: HashMap<&[Label], f64> = HashMap::new();
+                for &ngram in probs[level + 1].keys() {
+                    let suffix_prob = probs[level]
+                        .get(&ngram[1..])
+                        .ok_or_else(|| not_counted(level + 1, level + 2))?;
+                    *covered.entry(&ngram[..level + 1]).or_insert(0.0) += suffix_prob;
+                }
+                for &context in probs[level].keys() {
+                    let history = histories[level + 1]
+                        .get(context)
+                        .copied()
+                        .unwrap_or((0, 0.0));
+                    let covered = covered.get(context).copied().unwrap_or(0.0);
+                    let shorter_mass = if level == 0 {
+                        1.0
+                    } else {
+                        *masses[level - 1]
+                            .get(&context[1..])
+                            .ok_or_else(|| not_counted(level, level + 1))?
+                    };
+                    let (weight, mass) = backoff_weight(history, covered, shorter_mass);
+                    backoffs[level].insert(context, weight);
+                    masses[level].insert(context, mass);
+                }
+            }
+        }
+
+        Ok(probs
+            .iter()
+            .zip(&backoffs)
+            .map(|(probs, backoffs)| {
+                probs
+                    .iter()
+                    .map(|(&ngram, &prob)| {
+                        let backoff = backoffs.get(ngram).copied();
+                        (ngram.to_vec(), prob.ln() as LogProb, backoff)
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Estimate the probabilities and backoff weights with interpolated modified Kneser-Ney
+    /// smoothing
+    ///
+    /// The probability of an n-gram is its discounted count divided by the count of its context,
+    /// interpolated with the probability of its suffix. The unigrams are interpolated with the
+    /// uniform distribution. The backoff weight of a context is the mass freed by discounting,
+    /// which is the weight of the interpolation
+    fn kneser_ney_ngrams(&self) -> Result<Vec<Vec<NgramEntry>>, Error> {
+        let order = self.counts.len();
+        let counts = self.kneser_ney_counts();
+        let uniform = 1.0 / self.symt.len() as f64;
+
+        let mut probs: Vec<HashMap<&[Label], f64>> = Vec::with_capacity(order);
+        let mut backoffs: Vec<HashMap<&[Label], LogProb>> = vec![HashMap::new(); order];
+        for (level, counts) in counts.iter().enumerate() {
+            let discounts = kneser_ney_discounts(counts.values().copied());
+            let discount = |count: u64| match count {
+                0 => 0.0,
+                1 => discounts[0],
+                2 => discounts[1],
+                _ => discounts[2],
+            };
+
+            // The total count of each context and the mass freed by discounting its successors
+            // The whole count of a left out n-gram is freed
+            let mut contexts: HashMap<&[Label], (f64, f64)> = HashMap::new();
+            for (&ngram, &count) in counts {
+                let context = contexts.entry(&ngram[..level]).or_insert((0.0, 0.0));
+                context.0 += count as f64;
+                context.1 += if self.is_left_out(ngram, self.counts[level][ngram]) {
+                    count as f64
+                } else {
+                    discount(count).min(count as f64)
+                };
+            }
+
+            let mut level_probs = HashMap::with_capacity(counts.len());
+            for (&ngram, &count) in counts {
+                if self.is_left_out(ngram, self.counts[level][ngram]) {
+                    continue;
+                }
+                let (total, freed) = contexts[&ngram[..level]];
+                let shorter_prob = if level == 0 {
+                    uniform
+                } else {
+                    probs[level - 1]
+                        .get(&ngram[1..])
+                        .copied()
+                        .ok_or_else(|| not_counted(level, level + 1))?
+                };
+                let discounted = (count as f64 - discount(count)).max(0.0);
+                level_probs.insert(ngram, (discounted + freed * shorter_prob) / total);
+            }
+            if level > 0 {
+                for (&context, &(total, freed)) in &contexts {
+                    if probs[level - 1].contains_key(context) {
+                        backoffs[level - 1].insert(context, (freed / total).ln() as LogProb);
+                    }
+                }
+            }
+            probs.push(level_probs);
+        }
+
+        Ok(probs
+            .iter()
+            .zip(&backoffs)
+            .map(|(probs, backoffs)| {
+                probs
+                    .iter()
+                    .map(|(&ngram, &prob)| {
+                        let backoff = backoffs.get(ngram).copied();
+                        (ngram.to_vec(), prob.ln() as LogProb, backoff)
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Get the counts used by Kneser-Ney smoothing
+    ///
+    /// The n-grams of the highest order keep their counts. The n-grams of the lower orders are
+    /// counted by the number of different words preceding them, an n-gram at the start of a
+    /// sentence counts as preceded by one more word. It is at the start of a sentence if it was
+    /// counted more often than the n-grams one word longer ending with it
+    fn kneser_ney_counts(&self) -> Vec<HashMap<&[Label], u64>> {
+        let order = self.counts.len();
+        let mut kneser_ney_counts = Vec::with_capacity(order);
+        for (level, counts) in self.counts.iter().enumerate() {
+            if level + 1 == order {
+                kneser_ney_counts.push(
+                    counts
+                        .iter()
+                        .map(|(ngram, &count)| (&ngram[..], count))
+                        .collect(),
+                );
+                continue;
+            }
+            // The number of different words preceding each n-gram and how often it was preceded
+            let mut preceded: HashMap<&[Label], (u64, u64)> = HashMap::new();
+            for (ngram, &count) in &self.counts[level + 1] {
+                let entry = preceded.entry(&ngram[1..]).or_insert((0, 0));
+                entry.0 += 1;
+                entry.1 += count;
+            }
+            kneser_ney_counts.push(
+                counts
+                    .iter()
+                    .map(|(ngram, &count)| {
+                        let (no_preceding, preceded_count) =
+                            preceded.get(&ngram[..]).copied().unwrap_or((0, 0));
+                        let is_sentence_start = count > preceded_count;
+                        (&ngram[..], no_preceding + is_sentence_start as u64)
+                    })
+                    .collect(),
+            );
+        }
+        kneser_ney_counts
+    }
+
+    /// Check if an n-gram with the count is left out of the built model
+    fn is_left_out(&self, ngram: &[Label], count: u64) -> bool {
+        ngram.len() > 1 && count < self.min_count
+    }
+
+    /// Get the label of the symbol and add it to the symbol table if it is new
+    fn insert_symbol(&mut self, symbol: &str) -> Label {
+        if let Some(label) = self.symt.get_index_of(symbol) {
+            return label as Label;
+        }
+        self.symt.insert(symbol.to_string());
+        (self.symt.len() - 1) as Label
+    }
+}
+
+/// Log backoff weight of a context and the total probability of its distribution
+///
+/// The weight depends on how often the context was followed by a word and how much of this
+/// count was freed, the probability the shorter context assigns to the words kept in the context
+/// and the total probability of the shorter context
+fn backoff_weight(
+    (history_count, freed_count): (u64, f64),
+    covered: f64,
+    shorter_mass: f64,
+) -> (LogProb, f64) {
+    // A context that was never followed by a word leaves all of the probability to the
+    // shorter context
+    if history_count == 0 {
+        return (0.0, shorter_mass);
+    }
+    let freed = freed_count / history_count as f64;
+    let remaining = shorter_mass - covered;
+    // If the successors already cover the shorter context completely, the freed probability
+    // can not be distributed
+    if remaining <= 0.0 {
+        return (0.0, 1.0 - freed);
+    }
+    ((freed / remaining).ln() as LogProb, 1.0)
+}
+
+/// The error of an n-gram whose last words were not counted or were left out, so its
+/// probability can not be estimated
+fn not_counted(len: usize, order: usize) -> Error {
+    Error::Structure(format!(
+        "The last {} words of a {}-gram were not counted",
+        len, order
+    ))
+}
+
+/// Discounts of modified Kneser-Ney smoothing for n-grams counted once, twice and more often
+///
+/// They are estimated from the number of n-grams counted one to four times. If there are too
+/// few n-grams for this, every count gets the same discount, which is estimated from the number
+/// of n-grams counted once and twice if possible
+pub(crate) fn kneser_ney_discounts(counts: impl Iterator<Item = u64>) -> [f64; 3] {
+    let mut count_of_counts = [0u64; 4];
+    for count in counts {
+        if (1..=4).contains(&count) {
+            count_of_counts[count as usize - 1] += 1;
+        }
+    }
+    let [n1, n2, n3, n4] = count_of_counts.map(|no| no as f64);
+    if n1 == 0.0 {
+        return [KATZ_DISCOUNT; 3];
+    }
+    let y = n1 / (n1 + 2.0 * n2);
+    if n2 == 0.0 || n3 == 0.0 || n4 == 0.0 {
+        return [y; 3];
+    }
+    [
+        1.0 - 2.0 * y * n2 / n1,
+        2.0 - 3.0 * y * n3 / n2,
+        3.0 - 4.0 * y * n4 / n3,
+    ]
+    .map(|discount| discount.max(0.0))
+}
+
+/// An n-gram given by the labels of its words with its log probability and optional backoff
+/// weight
+pub(crate) type NgramEntry = (Vec<Label>, LogProb, Option<LogProb>);
+
+impl LanguageModel {
+    /// Lay out n-grams given by the labels of their words as a language model
+    ///
+    /// The n-grams are grouped by their order starting with the unigrams. There has to be a
+    /// unigram for each symbol, the n-grams may be in any order. Each n-gram links to its
+    /// children, which are sorted by their label, and each n-gram of an order above two links
+    /// to the n-gram made of all but its first word. The backoff weights are only stored if at
+    /// least one n-gram has one, missing weights are then set to ln(1). The n-grams of the
+    /// highest order have no backoff weights.
+    /// N-grams that are listed twice or can not be linked are an error
+    pub(crate) fn from_ngrams(
+        symt: IndexSet<Symbol>,
+        mut ngrams: Vec<Vec<NgramEntry>>,
+        smoothing: Smoothing,
+    ) -> Result<Self, Error> {
+        let order = ngrams.len();
+        if order == 0 {
+            return Err(Error::Structure(
+                "The order of a language model has to be at least one".to_string(),
+            ));
+        }
+        for (level, entries) in ngrams.iter_mut().enumerate() {
+            entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+            if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                let message = format!("A {}-gram is listed twice", level + 1);
+                return Err(Error::Structure(message));
+            }
+            if entries.iter().any(|(words, _, _)| words.len() != level + 1) {
+                let message = format!("A {}-gram does not have {} words", level + 1, level + 1);
+                return Err(Error::Structure(message));
+            }
+            if entries
+                .iter()
+                .any(|(words, _, _)| words[level] as usize >= symt.len())
+            {
+                let message = format!("A word of a {}-gram is not in the symbol table", level + 1);
+                return Err(Error::Structure(message));
+            }
+        }
+        let is_unigram_of_label =
+            |(label, (words, _, _)): (usize, &NgramEntry)| words[0] as usize == label;
+        if ngrams[0].len() != symt.len() || !ngrams[0].iter().enumerate().all(is_unigram_of_label) {
+            return Err(Error::Structure(
+                "There has to be exactly one unigram for each symbol".to_string(),
+            ));
+        }
+
+        let has_backoffs = ngrams
+            .iter()
+            .flatten()
+            .any(|(_, _, backoff)| backoff.is_some());
+
+        let mut levels: Vec<Level> = Vec::with_capacity(order);
+        for (level, entries) in ngrams.iter().enumerate() {
+            let backoffs = if has_backoffs && level + 1 < order {
+                entries
+                    .iter()
+                    .map(|(_, _, backoff)| backoff.unwrap_or(0.0))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            levels.push(Level {
+                ngrams: entries
+                    .iter()
+                    .map(|(words, log_prob, _)| (words[level], *log_prob))
+                    .collect(),
+                offsets: Indices::default(),
+                suffixes: Indices::default(),
+                backoffs,
+            });
+            if level == 0 {
+                continue;
+            }
+
+            // The n-grams of the level below link to the n-grams starting with them. Only the
+            // offset of the first child is stored, so the children of all contexts have to
+            // follow each other without gaps
+            let contexts = &ngrams[level - 1];
+            let mut offsets = Vec::with_capacity(contexts.len() + 1);
+            let mut child_idx = 0;
+            for (context, _, _) in contexts {
+                offsets.push(child_idx);
+                while child_idx < entries.len() && entries[child_idx].0[..level] == context[..] {
+                    child_idx += 1;
+                }
+            }
+            offsets.push(child_idx);
+            levels[level - 1].offsets = offsets.into_iter().collect();
+            if child_idx != entries.len() {
+                let message = format!(
+                    "The first {} words of a {}-gram are not a {}-gram",
+                    level,
+                    level + 1,
+                    level
+                );
+                return Err(Error::Structure(message));
+            }
+
+            // The n-grams of orders above two link to the n-gram made of all but their first
+            // word. For the highest order, it is the state the model transitions to after
+            // reading the n-gram
+            if level >= 2 {
+                let mut suffixes: Vec<usize> = Vec::with_capacity(entries.len());
+                for (words, _, _) in entries {
+                    let suffix_idx = contexts
+                        .binary_search_by(|(context, _, _)| context[..].cmp(&words[1..]))
+                        .map_err(|_| {
+                            let message = format!(
+                                "The last {} words of a {}-gram are not a {}-gram",
+                                level,
+                                level + 1,
+                                level
+                            );
+                            Error::Structure(message)
+                        })?;
+                    suffixes.push(suffix_idx);
+                }
+                levels[level].suffixes = suffixes.into_iter().collect();
+            }
+        }
+
+        Ok(Self {
+            symt,
+            levels,
+            smoothing,
+            sorted_labels: SortedLabels::default(),
+            ranked_labels: SortedLabels::default(),
+        })
+    }
+
+    /// Get all n-grams given by the labels of their words, the reverse of `from_ngrams`
+    ///
+    /// The n-grams of each order are sorted by their labels
+    pub(crate) fn to_ngrams(&self) -> Vec<Vec<NgramEntry>> {
+        // Follow the links from each n-gram to its children to find the words of the children
+        let mut words_of_level: Vec<Vec<Label>> = (0..self.symt.len() as Label)
+            .map(|label| vec![label])
+            .collect();
+        let mut ngrams = Vec::with_capacity(self.levels.len());
+        for (level_idx, level) in self.levels.iter().enumerate() {
+            let entries: Vec<NgramEntry> = level
+                .ngrams
+                .iter()
+                .zip(&words_of_level)
+                .enumerate()
+                .map(|(idx, (&(_, log_prob), words))| {
+                    (words.clone(), log_prob, level.backoffs.get(idx).copied())
+                })
+                .collect();
+
+            if let Some(next_level) = self.levels.get(level_idx + 1) {
+                let mut words_of_next_level = vec![Vec::new(); next_level.ngrams.len()];
+                for (idx, words) in words_of_level.iter().enumerate() {
+                    let children = level.children(idx);
+                    for (child, child_words) in next_level.ngrams[children.clone()]
+                        .iter()
+                        .zip(&mut words_of_next_level[children])
+                    {
+                        *child_words = [&words[..], &[child.0]].concat();
+                    }
+                }
+                words_of_level = words_of_next_level;
+            }
+            ngrams.push(entries);
+        }
+        for entries in &mut ngrams {
+            entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        }
+        ngrams
+    }
+}
@@ -0,0 +1,138 @@
+use super::*;
+
+/// The files a model is converted from
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ConvertInput {
+    /// The symbol table and the text files of the unigrams, bigrams and trigrams
+    Text {
+        symt: String,
+        unigrams: String,
+        bigrams: String,
+        trigrams: String,
+    },
+    /// A model in the ARPA format
+    Arpa(String),
+    /// A binary model, which may have been written by an older version
+    Binary(String),
+}
+
+/// The steps of a conversion, which are reported to the progress callback before they start
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ConvertStep {
+    /// Reading and parsing the input files
+    Reading,
+    /// Checking the structure of the model
+    Validating,
+    /// Compressing and writing the model
+    Writing,
+    /// The model was written
+    Finished,
+}
+
+/// The configuration of `LanguageModel::convert`
+pub struct ConvertConfig<'a> {
+    /// The files the model is read from
+    pub input: ConvertInput,
+    /// The file the binary model is written to
+    pub output: String,
+    /// How the lines of text files that can not be parsed are handled
+    pub mode: ParseMode,
+    /// The level of the compression from 0 for none to 9 for the best one
+    pub compression_level: u32,
+    /// Validate the structure of the model before writing it
+    pub validate: bool,
+    /// Called with each step of the conversion before it starts
+    pub progress: Option<Box<dyn FnMut(ConvertStep) + 'a>>,
+}
+
+impl<'a> ConvertConfig<'a> {
+    /// Create a configuration that reads the input strictly, writes it with the default
+    /// compression and does not validate it
+    pub fn new(input: ConvertInput, output: &str) -> Self {
+        Self {
+            input,
+            output: output.to_string(),
+            mode: ParseMode::Strict,
+            compression_level: Compression::default().level(),
+            validate: false,
+            progress: None,
+        }
+    }
+
+    /// Create a configuration for the text files of a folder, which are named `symt.txt`,
+    /// `1gms.txt`, `2gms.txt` and `3gms.txt`
+    pub fn from_text_folder(folder: &str, output: &str) -> Self {
+        let path = |fname: &str| Path::new(folder).join(fname).to_string_lossy().into_owned();
+        let input = ConvertInput::Text {
+            symt: path("symt.txt"),
+            unigrams: path("1gms.txt"),
+            bigrams: path("2gms.txt"),
+            trigrams: path("3gms.txt"),
+        };
+        Self::new(input, output)
+    }
+}
+
+impl fmt::Debug for ConvertConfig<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConvertConfig")
+            .field("input", &self.input)
+            .field("output", &self.output)
+            .field("mode", &self.mode)
+            .field("compression_level", &self.compression_level)
+            .field("validate", &self.validate)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl LanguageModel {
+    /// Read a model and write it as a compressed binary file
+    ///
+    /// Nothing is printed, the steps are reported to the progress callback instead. In lenient
+    /// mode, the problems found in text files are returned in the report
+    pub fn convert(mut config: ConvertConfig) -> Result<ParseReport, Error> {
+        let mut report_step = |step| {
+            if let Some(progress) = &mut config.progress {
+                progress(step);
+            }
+        };
+        if config.compression_level > 9 {
+            return Err(Error::Config(format!(
+                "The compression level {} is not between 0 and 9",
+                config.compression_level
+            )));
+        }
+
+        report_step(ConvertStep::Reading);
+        let options = ReadOptions {
+            mode: config.mode,
+            validate: false,
+        };
+        let (language_model, report) = match &config.input {
+            ConvertInput::Text {
+                symt,
+                unigrams,
+                bigrams,
+                trigrams,
+            } => Self::read_from_text_with_options(symt, unigrams, bigrams, trigrams, options)?,
+            ConvertInput::Arpa(fname) => (Self::read_arpa(fname)?, ParseReport::default()),
+            ConvertInput::Binary(fname) => (Self::read(fname)?, ParseReport::default()),
+        };
+
+        if config.validate {
+            report_step(ConvertStep::Validating);
+            language_model.check()?;
+        }
+
+        report_step(ConvertStep::Writing);
+        let file = File::create(&config.output)?;
+        header::write_model(
+            &language_model,
+            BufWriter::new(file),
+            Compression::new(config.compression_level),
+        )?;
+        report_step(ConvertStep::Finished);
+        Ok(report)
+    }
+}
@@ -0,0 +1,78 @@
+use std::{fmt, io};
+
+use super::Violation;
+
+/// The errors that can occur while reading, writing or building a language model
+#[derive(Debug)]
+pub enum Error {
+    /// A file could not be opened, read or written
+    Io(io::Error),
+    /// A line of a text file could not be parsed
+    Parse {
+        fname: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// The file is not a language model in a supported format
+    Format { fname: String, message: String },
+    /// The n-grams do not form a consistent language model
+    Structure(String),
+    /// The validation of the language model found violations of its structure
+    Validation(Vec<Violation>),
+    /// The language model could not be serialized or deserialized
+    Serialization(bincode::Error),
+    /// An option of an operation has a value it does not support
+    Config(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+            Error::Parse {
+                fname,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", fname, line, column, message),
+            Error::Format { fname, message } => write!(f, "{}: {}", fname, message),
+            Error::Structure(message) => write!(f, "Inconsistent language model: {}", message),
+            Error::Validation(violations) => {
+                write!(f, "Invalid language model:")?;
+                for violation in violations {
+                    write!(f, "\n  {}", violation)?;
+                }
+                Ok(())
+            }
+            Error::Serialization(error) => write!(f, "Serialization error: {}", error),
+            Error::Config(message) => write!(f, "Invalid configuration: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Serialization(error) => Some(error),
+            Error::Parse { .. }
+            | Error::Format { .. }
+            | Error::Structure(_)
+            | Error::Validation(_)
+            | Error::Config(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(error: bincode::Error) -> Self {
+        Error::Serialization(error)
+    }
+}
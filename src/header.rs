@@ -0,0 +1,167 @@
+use std::{
+    io::{self, BufRead, Cursor, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::Crc;
+
+use super::*;
+
+// Files written with a header start with the magic bytes, which are followed by the header and
+// the compressed model. Files of older versions are compressed as a whole and start with the
+// magic bytes of gzip instead
+const MAGIC: &[u8; 8] = b"LMBINARY";
+const GZIP_MAGIC: &[u8; 2] = &[0x1f, 0x8b];
+
+/// The version of the file format written by `LanguageModel::write`
+///
+/// Version 1 is the first format with a header. The files written before it are version 0,
+/// they have no header and only store trigram models without backoff weights. They are
+/// converted when they are read
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The metadata in front of a language model written by `LanguageModel::write`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct FileHeader {
+    /// The version of the file format
+    pub version: u32,
+    /// The number of words of the longest n-grams
+    pub order: usize,
+    /// The number of symbols in the symbol table
+    pub vocabulary_size: usize,
+    /// The way backoffs are penalized
+    pub smoothing: Smoothing,
+    /// The time the file was written in seconds since the Unix epoch
+    pub created: u64,
+    /// The name and version of the crate that wrote the file
+    pub creator: String,
+    /// The CRC-32 checksum of the uncompressed model
+    pub checksum: u32,
+}
+
+impl FileHeader {
+    fn new(language_model: &LanguageModel, checksum: u32) -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        Self {
+            version: FORMAT_VERSION,
+            order: language_model.order(),
+            vocabulary_size: language_model.symt.len(),
+            smoothing: language_model.smoothing,
+            created,
+            creator: concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")).to_string(),
+            checksum,
+        }
+    }
+}
+
+impl LanguageModel {
+    /// Read the header of a file written by `LanguageModel::write` without reading the model
+    /// Files of older versions have no header, which is reported as a format error
+    pub fn read_header(fname: &str) -> Result<FileHeader, Error> {
+        read_header(&mut BufReader::new(File::open(fname)?), fname)
+    }
+
+    /// Read the header of a model provided by a reader
+    /// Only the bytes of the header are consumed
+    pub fn read_header_from<R: Read>(mut reader: R) -> Result<FileHeader, Error> {
+        read_header(&mut reader, "reader")
+    }
+}
+
+fn read_header<R: Read>(reader: &mut R, fname: &str) -> Result<FileHeader, Error> {
+    if read_prefix(reader)? != MAGIC {
+        return Err(format_error(
+            fname,
+            "The file has no header, it is not a language model or was written by an older \
+             version",
+        ));
+    }
+    let header = bincode::deserialize_from(reader)?;
+    check_version(&header, fname)?;
+    Ok(header)
+}
+
+/// Serialize the language model and write it with a header, the model is compressed with the
+/// compression level
+pub(crate) fn write_model<W: Write>(
+    language_model: &LanguageModel,
+    mut writer: W,
+    compression: Compression,
+) -> Result<(), Error> {
+    let bytes = bincode::serialize(language_model)?;
+    let header = FileHeader::new(language_model, checksum(&bytes));
+    writer.write_all(MAGIC)?;
+    bincode::serialize_into(&mut writer, &header)?;
+    let mut encoder = GzEncoder::new(writer, compression);
+    encoder.write_all(&bytes)?;
+    encoder.finish()?.flush()?;
+    Ok(())
+}
+
+/// Read a language model written with a header or by an older version
+/// The name of the file is only used for the errors
+pub(crate) fn read_model<R: BufRead>(mut reader: R, fname: &str) -> Result<LanguageModel, Error> {
+    let prefix = read_prefix(&mut reader)?;
+    if prefix != MAGIC {
+        if !prefix.starts_with(GZIP_MAGIC) {
+            return Err(format_error(fname, "The file is not a language model"));
+        }
+        // The bytes read belong to the compressed model
+        return legacy::read_compressed_model(Cursor::new(prefix).chain(reader));
+    }
+
+    let header: FileHeader = bincode::deserialize_from(&mut reader)?;
+    check_version(&header, fname)?;
+    let mut bytes = Vec::new();
+    GzDecoder::new(reader).read_to_end(&mut bytes)?;
+    if checksum(&bytes) != header.checksum {
+        return Err(format_error(
+            fname,
+            "The checksum of the model does not match its header, the file is corrupted",
+        ));
+    }
+    let language_model: LanguageModel = bincode::deserialize(&bytes)?;
+    if language_model.order() != header.order
+        || language_model.symt.len() != header.vocabulary_size
+        || language_model.smoothing != header.smoothing
+    {
+        return Err(format_error(fname, "The model does not match its header"));
+    }
+    Ok(language_model)
+}
+
+/// Reject files of newer versions, their layout is not known
+fn check_version(header: &FileHeader, fname: &str) -> Result<(), Error> {
+    if header.version > FORMAT_VERSION {
+        return Err(format_error(
+            fname,
+            &format!(
+                "The file has the format version {}, but only versions up to {} are supported",
+                header.version, FORMAT_VERSION
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Read as many bytes as the magic bytes are long or less if the file is shorter
+fn read_prefix<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut prefix = Vec::with_capacity(MAGIC.len());
+    reader.take(MAGIC.len() as u64).read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(bytes);
+    crc.sum()
+}
+
+fn format_error(fname: &str, message: &str) -> Error {
+    Error::Format {
+        fname: fname.to_string(),
+        message: message.to_string(),
+    }
+}
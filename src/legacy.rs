@@ -0,0 +1,85 @@
+use std::io::BufRead;
+
+use super::*;
+
+// The layout of the files written before the files had a header, which only stored trigram
+// models with the symbol table and the n-grams
+
+#[derive(Deserialize)]
+struct TrigramModel {
+    symt: IndexSet<String>,
+    unigrams: Vec<(f32, u32, u16)>,
+    bigrams: Vec<(u32, f32, u32, u16)>,
+    trigrams: Vec<(u32, f32, u32)>,
+}
+
+impl TryFrom<TrigramModel> for LanguageModel {
+    type Error = Error;
+
+    fn try_from(model: TrigramModel) -> Result<Self, Error> {
+        // The unigrams are indexed by their label and the trigrams link to their suffix bigram
+        // The offset and number of the children are turned into start offsets
+        let children = |offset: u32, no: u16| (offset as usize, no as usize);
+        let unigrams = Level {
+            offsets: Indices::from_children(
+                &model
+                    .unigrams
+                    .iter()
+                    .map(|&(_, offset, no)| children(offset, no))
+                    .collect::<Vec<_>>(),
+            )?,
+            ngrams: model
+                .unigrams
+                .into_iter()
+                .enumerate()
+                .map(|(label, (log_prob, _, _))| (label as Label, log_prob))
+                .collect(),
+            suffixes: Indices::default(),
+            backoffs: Vec::new(),
+        };
+        let bigrams = Level {
+            offsets: Indices::from_children(
+                &model
+                    .bigrams
+                    .iter()
+                    .map(|&(_, _, offset, no)| children(offset, no))
+                    .collect::<Vec<_>>(),
+            )?,
+            ngrams: model
+                .bigrams
+                .into_iter()
+                .map(|(label, log_prob, _, _)| (label, log_prob))
+                .collect(),
+            suffixes: Indices::default(),
+            backoffs: Vec::new(),
+        };
+        let trigrams = Level {
+            ngrams: model
+                .trigrams
+                .iter()
+                .map(|&(label, log_prob, _)| (label, log_prob))
+                .collect(),
+            offsets: Indices::default(),
+            suffixes: model
+                .trigrams
+                .iter()
+                .map(|&(_, _, suffix)| suffix as usize)
+                .collect(),
+            backoffs: Vec::new(),
+        };
+
+        Ok(Self {
+            symt: model.symt,
+            levels: vec![unigrams, bigrams, trigrams],
+            smoothing: Smoothing::StupidBackoff,
+            sorted_labels: SortedLabels::default(),
+            ranked_labels: SortedLabels::default(),
+        })
+    }
+}
+
+/// Read a compressed file written before the files had a header
+pub(crate) fn read_compressed_model<R: BufRead>(reader: R) -> Result<LanguageModel, Error> {
+    let model: TrigramModel = bincode::deserialize_from(GzDecoder::new(reader))?;
+    model.try_into()
+}
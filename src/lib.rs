@@ -4,329 +4,1129 @@ extern crate bincode;
 
 use flate2::{bufread::GzDecoder, write::GzEncoder, Compression};
 use indexmap::IndexSet;
-use std::{cmp::Ordering, collections::HashMap, fs::File, io::BufReader, iter::FromIterator};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    iter::FromIterator,
+    ops::Range,
+    path::Path,
+    sync::OnceLock,
+};
 
 pub mod utilities;
 use utilities::*;
+pub use utilities::{ParseMode, ParseReport};
+
+mod convert;
+pub use convert::{ConvertConfig, ConvertInput, ConvertStep};
+
+mod error;
+pub use error::Error;
+
+mod header;
+pub use header::{FileHeader, FORMAT_VERSION};
+
+mod arpa;
+mod builder;
+mod legacy;
+pub use builder::LanguageModelBuilder;
+
+mod mapped;
+pub use mapped::MappedLanguageModel;
+
+mod pruning;
+pub use pruning::PruneReport;
+
+mod stats;
+pub use stats::{LogProbStats, ModelStats, OrderStats};
+
+mod scoring;
+pub use scoring::{CorpusScore, SequenceScore, TokenScore};
+
+mod validation;
+pub use validation::Violation;
+
+mod vocabulary;
 
 #[cfg(test)]
 mod tests;
 
-const BACKOFF_WEIGHT: f32 = -0.916290731874155; // ln(0.4)
+const BACKOFF_WEIGHT: f32 = -0.916_290_76; // ln(0.4)
+
+/// The symbol words that are not in the symbol table are read as, if the model has it
+pub const UNKNOWN: &str = "<unk>";
+/// The symbol read at the start of a sentence, if the model has it
+pub const SENTENCE_START: &str = "<s>";
+/// The symbol scored at the end of a sentence, if the model has it
+pub const SENTENCE_END: &str = "</s>";
 
 type Symbol = String;
-type Label = u32;
-type LogProb = f32;
-type Offset = u32;
-type NoOfNgrams = u16;
+/// The index of a symbol in the symbol table
+pub type Label = u32;
+/// The natural logarithm of a probability
+pub type LogProb = f32;
 
-type Unigram = (LogProb, Offset, NoOfNgrams);
-type Bigram = (Label, LogProb, Offset, NoOfNgrams);
-type Trigram = (Label, LogProb, Offset);
+/// An n-gram given by the label of its last word and its log probability
+type Ngram = (Label, LogProb);
 
-#[derive(Copy, Clone, PartialEq, Debug)]
-enum LMContext {
-    Zero,
-    One,
-    Two,
+/// Indices into a level of the model
+/// They are stored with 32 bits unless one of them does not fit
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+enum Indices {
+    Narrow(Vec<u32>),
+    Wide(Vec<u64>),
+}
+
+impl Default for Indices {
+    fn default() -> Self {
+        Indices::Narrow(Vec::new())
+    }
+}
+
+impl FromIterator<usize> for Indices {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let indices: Vec<usize> = iter.into_iter().collect();
+        match indices
+            .iter()
+            .map(|&idx| u32::try_from(idx))
+            .collect::<Result<Vec<u32>, _>>()
+        {
+            Ok(narrow) => Indices::Narrow(narrow),
+            Err(_) => Indices::Wide(indices.into_iter().map(|idx| idx as u64).collect()),
+        }
+    }
+}
+
+impl Indices {
+    fn len(&self) -> usize {
+        match self {
+            Indices::Narrow(indices) => indices.len(),
+            Indices::Wide(indices) => indices.len(),
+        }
+    }
+
+    fn get(&self, idx: usize) -> Option<usize> {
+        match self {
+            Indices::Narrow(indices) => indices.get(idx).map(|&idx| idx as usize),
+            Indices::Wide(indices) => indices.get(idx).map(|&idx| idx as usize),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len()).filter_map(|idx| self.get(idx))
+    }
+
+    /// Get the number of bytes used by the indices
+    fn memory_size(&self) -> usize {
+        match self {
+            Indices::Narrow(indices) => indices.len() * std::mem::size_of::<u32>(),
+            Indices::Wide(indices) => indices.len() * std::mem::size_of::<u64>(),
+        }
+    }
+
+    /// Get the start offsets of children given by their offset and number
+    /// The children have to follow each other without gaps. The offset of a context without
+    /// children does not matter
+    fn from_children(children: &[(usize, usize)]) -> Result<Self, Error> {
+        let mut offsets = Vec::with_capacity(children.len() + 1);
+        let mut end = children
+            .iter()
+            .find(|&&(_, no)| no > 0)
+            .map_or(0, |&(offset, _)| offset);
+        for &(offset, no) in children {
+            if no > 0 && offset != end {
+                return Err(Error::Structure(format!(
+                    "The children at offset {} do not follow the previous children ending at {}",
+                    offset, end
+                )));
+            }
+            offsets.push(end);
+            end += no;
+        }
+        offsets.push(end);
+        Ok(offsets.into_iter().collect())
+    }
+}
+
+/// The n-grams of one order
+///
+/// The n-grams are sorted by their words, so the children of each n-gram are next to each other
+/// and sorted by their label. Only the offset of the first child of each n-gram is stored, its
+/// children end where the children of the next n-gram start
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+struct Level {
+    ngrams: Vec<Ngram>,
+    // The offset of the children of each n-gram in the next level followed by the end of the
+    // children of the last n-gram. It is empty for the highest order, which has no children
+    offsets: Indices,
+    // Index of the n-gram made of all but the first word in the level below. It is only stored
+    // for orders above two, the suffix of a bigram is the unigram of its label
+    suffixes: Indices,
+    // Backoff weights of the n-grams, empty if the model has none or for the highest order
+    backoffs: Vec<LogProb>,
+}
+
+impl Level {
+    /// Get the range of the children of the n-gram in the next level
+    fn children(&self, idx: usize) -> Range<usize> {
+        match (self.offsets.get(idx), self.offsets.get(idx + 1)) {
+            (Some(start), Some(end)) => start..end,
+            _ => 0..0,
+        }
+    }
+
+    /// Get the number of bytes used by the n-grams, their links and backoff weights
+    fn memory_size(&self) -> usize {
+        self.ngrams.len() * std::mem::size_of::<Ngram>()
+            + self.offsets.memory_size()
+            + self.suffixes.memory_size()
+            + self.backoffs.len() * std::mem::size_of::<LogProb>()
+    }
+}
+
+/// The way the probability of a word is penalized when the model has to backoff to a shorter
+/// context to find it
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Smoothing {
+    /// Every backoff is penalized with the constant weight ln(0.4). The probabilities are not
+    /// normalized
+    #[default]
+    StupidBackoff,
+    /// Every backoff is penalized with the backoff weight of the context the model backs off
+    /// from. Contexts without a stored weight are not penalized
+    Katz,
+    /// Backoffs are penalized like with Katz backoff, but the probabilities and backoff weights
+    /// are estimated with interpolated modified Kneser-Ney smoothing when training
+    KneserNey,
+}
+
+/// Options for reading a language model
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ReadOptions {
+    /// How lines that can not be parsed are handled when reading text files
+    pub mode: ParseMode,
+    /// Validate the structure of the model after reading it
+    pub validate: bool,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct LMState {
     last_processed_label: Label,
+    // Index of the n-gram that is the context of the state in the level of its order
+    context_idx: usize,
     ngrams_offset: usize,
     ngrams_no: usize,
-    context_len: LMContext,
+    // Number of words of the context
+    context_len: usize,
+    // The sentence start symbol has not been read yet
+    at_sentence_start: bool,
 }
 
 impl Default for LMState {
     fn default() -> Self {
         Self {
             last_processed_label: 0,
+            context_idx: 0,
             ngrams_offset: 0,
             ngrams_no: usize::MAX,
-            context_len: LMContext::Zero,
+            context_len: 0,
+            at_sentence_start: false,
+        }
+    }
+}
+
+impl LMState {
+    /// Get the state at the start of a sentence
+    ///
+    /// The model reads the sentence start symbol `<s>` before the first word, so the words
+    /// likely to start a sentence are predicted. If the model has no such symbol, this is the
+    /// initial state
+    pub fn sentence_start() -> Self {
+        Self {
+            at_sentence_start: true,
+            ..Self::default()
         }
     }
+
+    /// Get the number of words of the context the predictions of the state are based on
+    /// The sentence start symbol counts as a word once it was read
+    pub fn context_len(&self) -> usize {
+        self.context_len
+    }
+}
+
+/// The labels of the symbol table in an order derived from the model, like sorted by their
+/// symbol. It is built on first use and not serialized
+#[derive(Default, Debug)]
+struct SortedLabels(OnceLock<Vec<Label>>);
+
+impl PartialEq for SortedLabels {
+    // The sorted labels are derived from the model, so they are always equal if the models
+    // are
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct LanguageModel {
     symt: IndexSet<String>,
-    unigrams: Vec<Unigram>,
-    bigrams: Vec<Bigram>,
-    trigrams: Vec<Trigram>,
+    // The n-grams of each order, starting with the unigrams, which are indexed by their label
+    levels: Vec<Level>,
+    smoothing: Smoothing,
+    // The labels sorted by their symbol, so all symbols starting with a prefix are next to each
+    // other
+    #[serde(skip)]
+    sorted_labels: SortedLabels,
+    // The labels sorted by their unigram probability from high to low
+    #[serde(skip)]
+    ranked_labels: SortedLabels,
 }
 impl LanguageModel {
     /// Read the language model from text files
-    /// The filename of the symbol table and the n-grams must be provided
+    /// The filename of the symbol table and the n-grams must be provided. The text files
+    /// describe a trigram model
     pub fn read_from_text(
         fname_symt: &str,
         fname_unigrams: &str,
         fname_bigrams: &str,
         fname_trigrams: &str,
-    ) -> Self {
+    ) -> Result<Self, Error> {
+        let (language_model, _) = Self::read_from_text_with_options(
+            fname_symt,
+            fname_unigrams,
+            fname_bigrams,
+            fname_trigrams,
+            ReadOptions::default(),
+        )?;
+        Ok(language_model)
+    }
+
+    /// Read the language model from text files
+    /// In lenient mode, the problems found in the files are returned in the report
+    /// Skipping a line shifts the index of all following lines, so the model should be
+    /// validated if the report is not clean
+    pub fn read_from_text_with_options(
+        fname_symt: &str,
+        fname_unigrams: &str,
+        fname_bigrams: &str,
+        fname_trigrams: &str,
+        options: ReadOptions,
+    ) -> Result<(Self, ParseReport), Error> {
+        let mode = options.mode;
+        Self::from_text_iterators(
+            SymtIterator::with_mode(fname_symt, mode)?,
+            UnigramIterator::with_mode(fname_unigrams, mode)?,
+            BigramIterator::with_mode(fname_bigrams, mode)?,
+            TrigramIterator::with_mode(fname_trigrams, mode)?,
+            options,
+        )
+    }
+
+    /// Read the language model from readers providing the text files
+    /// The readers are named after their content in the errors, for example "unigrams"
+    pub fn read_from_text_readers(
+        symt: impl Read,
+        unigrams: impl Read,
+        bigrams: impl Read,
+        trigrams: impl Read,
+        options: ReadOptions,
+    ) -> Result<(Self, ParseReport), Error> {
+        let mode = options.mode;
+        Self::from_text_iterators(
+            SymtIterator::from_reader(symt, "symbol table", mode),
+            UnigramIterator::from_reader(unigrams, "unigrams", mode),
+            BigramIterator::from_reader(bigrams, "bigrams", mode),
+            TrigramIterator::from_reader(trigrams, "trigrams", mode),
+            options,
+        )
+    }
+
+    fn from_text_iterators(
+        mut symt_iterator: SymtIterator<impl Read>,
+        mut unigram_iterator: UnigramIterator<impl Read>,
+        mut bigram_iterator: BigramIterator<impl Read>,
+        mut trigram_iterator: TrigramIterator<impl Read>,
+        options: ReadOptions,
+    ) -> Result<(Self, ParseReport), Error> {
+        let mut report = ParseReport::default();
+
         // Load the symbol table
         let mut symt = IndexSet::new();
-        for symbol in SymtIterator::new(fname_symt) {
-            symt.insert(symbol);
+        for symbol in symt_iterator.by_ref() {
+            symt.insert(symbol?);
         }
         symt.shrink_to_fit();
-        // Load the unigrams
+        report.warnings.append(&mut symt_iterator.warnings());
+        // Load the unigrams, their label is their index
         let mut unigrams = Vec::new();
-        for unigram in UnigramIterator::new(fname_unigrams) {
-            unigrams.push(unigram);
+        let mut unigram_children = Vec::new();
+        for unigram in unigram_iterator.by_ref() {
+            let (log_prob, offset, no) = unigram?;
+            unigrams.push((unigrams.len() as Label, log_prob));
+            unigram_children.push((offset, no));
         }
-        unigrams.shrink_to_fit();
+        report.warnings.append(&mut unigram_iterator.warnings());
         // Load the bigrams
         let mut bigrams = Vec::new();
-        for bigram in BigramIterator::new(fname_bigrams) {
-            bigrams.push(bigram);
+        let mut bigram_children = Vec::new();
+        for bigram in bigram_iterator.by_ref() {
+            let (label, log_prob, offset, no) = bigram?;
+            bigrams.push((label, log_prob));
+            bigram_children.push((offset, no));
         }
-        bigrams.shrink_to_fit();
-        // Load the trigrams
+        report.warnings.append(&mut bigram_iterator.warnings());
+        // Load the trigrams, they link to their suffix instead of children
         let mut trigrams = Vec::new();
-        for trigram in TrigramIterator::new(fname_trigrams) {
-            trigrams.push(trigram);
+        let mut trigram_suffixes = Vec::new();
+        for trigram in trigram_iterator.by_ref() {
+            let (label, log_prob, suffix) = trigram?;
+            trigrams.push((label, log_prob));
+            trigram_suffixes.push(suffix);
         }
-        trigrams.shrink_to_fit();
+        report.warnings.append(&mut trigram_iterator.warnings());
 
-        Self {
+        let unigrams = Level {
+            ngrams: unigrams,
+            offsets: Indices::from_children(&unigram_children)?,
+            ..Default::default()
+        };
+        let bigrams = Level {
+            ngrams: bigrams,
+            offsets: Indices::from_children(&bigram_children)?,
+            ..Default::default()
+        };
+        let trigrams = Level {
+            ngrams: trigrams,
+            suffixes: trigram_suffixes.into_iter().collect(),
+            ..Default::default()
+        };
+        let language_model = Self {
             symt,
-            unigrams,
-            bigrams,
-            trigrams,
+            levels: vec![unigrams, bigrams, trigrams],
+            smoothing: Smoothing::StupidBackoff,
+            sorted_labels: SortedLabels::default(),
+            ranked_labels: SortedLabels::default(),
+        };
+        if options.validate {
+            language_model.check()?;
         }
+        Ok((language_model, report))
+    }
+
+    /// Train a language model on a text corpus
+    /// Each line of the corpus is a sentence, the words are separated by whitespace
+    pub fn train(corpus: &str) -> Result<Self, Error> {
+        LanguageModelBuilder::new().add_text(corpus).build()
     }
 
     /// Serialize the language model, compress and write it to a file
-    pub fn write(&self, fname: &str) -> Result<(), Box<bincode::ErrorKind>> {
-        let file = File::create(fname).unwrap();
-        let encoder = GzEncoder::new(file, Compression::default());
-        bincode::serialize_into(encoder, self)
+    /// The file starts with a header, which describes the model and holds its checksum
+    pub fn write(&self, fname: &str) -> Result<(), Error> {
+        let file = File::create(fname)?;
+        self.write_to(BufWriter::new(file))
+    }
+
+    /// Serialize the language model, compress and write it to a writer
+    /// The bytes are the same as the ones of a file written by `write`
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), Error> {
+        header::write_model(self, writer, Compression::default())
     }
 
     /// Read the language model from a compressed file and deserialize it
-    pub fn read(fname: &str) -> Result<Self, Box<bincode::ErrorKind>> {
-        let file = File::open(fname).unwrap();
-        let buf_reader = BufReader::new(file);
-        let decoder = GzDecoder::new(buf_reader);
-        bincode::deserialize_from(decoder)
-    }
-
-    /// Get the predictions for the current state
-    pub fn predict(&self, lm_state: LMState, max_no_predictions: usize) -> Vec<(&str, LogProb)> {
-        let mut lm_state = lm_state;
-
-        let mut predictions = HashMap::with_capacity(max_no_predictions);
-        let mut backoff = 0;
-
-        // If the current state is associated with bigrams...
-        if lm_state.context_len == LMContext::Two {
-            // .. read the information of all outgoing transitions from the trigram vec
-            for (label, log_prob, _) in self
-                .trigrams
-                .iter()
-                .skip(lm_state.ngrams_offset)
-                .take(lm_state.ngrams_no)
-            {
-                // add the label and its likelihood to the HashMap storing the predictions
-                predictions.entry(*label).or_insert(*log_prob);
+    pub fn read(fname: &str) -> Result<Self, Error> {
+        Self::read_with_options(fname, ReadOptions::default())
+    }
+
+    /// Read the language model from a compressed file and deserialize it
+    /// Files of older format versions are converted, writing the model again stores it in the
+    /// current version. Files of newer versions and files whose checksum does not match are
+    /// rejected with a format error
+    /// The parse mode of the options is not used because the file is not a text file
+    pub fn read_with_options(fname: &str, options: ReadOptions) -> Result<Self, Error> {
+        let file = File::open(fname)?;
+        let language_model = header::read_model(BufReader::new(file), fname)?;
+        if options.validate {
+            language_model.check()?;
+        }
+        Ok(language_model)
+    }
+
+    /// Read the language model from a reader providing the bytes of a file written by `write`
+    /// Models embedded with `include_bytes!` can be read from the byte slice. Older versions
+    /// are converted like they are when reading a file
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, Error> {
+        header::read_model(BufReader::new(reader), "reader")
+    }
+
+    /// Get the order of the language model, the number of words of its longest n-grams
+    pub fn order(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Get the number of n-grams of each order, starting with the unigrams
+    pub fn no_ngrams(&self) -> Vec<usize> {
+        self.levels.iter().map(|level| level.ngrams.len()).collect()
+    }
+
+    /// Set the way backoffs are penalized
+    pub fn set_smoothing(&mut self, smoothing: Smoothing) {
+        self.smoothing = smoothing;
+    }
+
+    /// Get the labels sorted by their symbol, so all symbols starting with a prefix are next to
+    /// each other
+    fn sorted_labels(&self) -> &[Label] {
+        self.sorted_labels.0.get_or_init(|| {
+            let mut sorted_labels: Vec<Label> = (0..self.symt.len() as Label).collect();
+            sorted_labels.sort_by_key(|&label| &self.symt[label as usize]);
+            sorted_labels
+        })
+    }
+
+    /// Get the labels sorted by their unigram probability from high to low
+    /// Labels with the same probability are sorted by their label
+    fn ranked_labels(&self) -> &[Label] {
+        self.ranked_labels.0.get_or_init(|| {
+            let unigrams = &self.levels[0].ngrams;
+            let mut ranked_labels: Vec<Label> = (0..unigrams.len() as Label).collect();
+            ranked_labels
+                .sort_by(|&a, &b| by_log_prob(&unigrams[a as usize], &unigrams[b as usize]));
+            ranked_labels
+        })
+    }
+}
+
+/// Implement the methods to query a model on top of its n-grams, so the models in memory and the
+/// memory-mapped models share them
+macro_rules! impl_queries {
+    ($model:ty) => {
+        impl $model {
+            /// Get the way backoffs are penalized
+            pub fn smoothing(&self) -> Smoothing {
+                Ngrams::smoothing(self)
+            }
+
+            /// Get the predictions for the current state
+            pub fn predict(
+                &self,
+                lm_state: LMState,
+                max_no_predictions: usize,
+            ) -> Vec<(&str, LogProb)> {
+                self.predictions(lm_state, "", max_no_predictions)
+            }
+
+            /// Get the predictions for the current state that start with the prefix
+            /// This completes a partially entered word
+            pub fn predict_with_prefix(
+                &self,
+                lm_state: LMState,
+                prefix: &str,
+                max_no_predictions: usize,
+            ) -> Vec<(&str, LogProb)> {
+                self.predictions(lm_state, prefix, max_no_predictions)
+            }
+
+            /// Write the labels of the predictions for the current state into the buffer
+            ///
+            /// The buffer is cleared first. The predictions are the same as the ones of `predict`,
+            /// but no memory is allocated once the buffer has grown large enough
+            pub fn predict_labels(
+                &self,
+                lm_state: LMState,
+                max_no_predictions: usize,
+                predictions: &mut Vec<(Label, LogProb)>,
+            ) {
+                self.label_predictions_into(lm_state, "", max_no_predictions, predictions)
+            }
+
+            /// Write the labels of the predictions for the current state that start with the prefix
+            /// into the buffer, like `predict_labels`
+            pub fn predict_labels_with_prefix(
+                &self,
+                lm_state: LMState,
+                prefix: &str,
+                max_no_predictions: usize,
+                predictions: &mut Vec<(Label, LogProb)>,
+            ) {
+                self.label_predictions_into(lm_state, prefix, max_no_predictions, predictions)
+            }
+
+            /// Lazily get the labels and log probabilities of every word for the current state
+            ///
+            /// The words following the context of the state come first in the order of their label,
+            /// followed by the words following the shorter contexts the model backs off to. The
+            /// other words come last, ordered by their unigram probability from high to low.
+            /// Nothing is allocated, so taking the first predictions is cheap
+            pub fn iter_label_predictions(
+                &self,
+                lm_state: LMState,
+            ) -> impl Iterator<Item = (Label, LogProb)> + '_ {
+                self.label_predictions_iter(lm_state)
+            }
+
+            /// Get the log probability stored for the n-gram of the words, without backing off
+            ///
+            /// None is returned if the model has no such n-gram, for example because one of the
+            /// words is not in the symbol table or there are more words than the order of the model
+            pub fn ngram_log_prob(&self, symbols: &[&str]) -> Option<LogProb> {
+                self.stored_log_prob(symbols)
+            }
+
+            /// Lazily get the words and log probabilities of all n-grams stored in the model
+            ///
+            /// Each n-gram is followed by the n-grams extending it by one word, the words following
+            /// the same n-gram are in the order of their label
+            pub fn iter_ngrams(&self) -> impl Iterator<Item = (Vec<&str>, LogProb)> + '_ {
+                self.stored_ngrams_iter()
+            }
+
+            /// Get the symbol of the label or None if the label is not in the symbol table
+            pub fn symbol(&self, label: Label) -> Option<&str> {
+                ((label as usize) < self.no_symbols()).then(|| Ngrams::symbol(self, label))
             }
-            // If not enough predictions were found, backoff and continue from the new state
-            if predictions.len() < max_no_predictions {
-                lm_state = self.backoff(lm_state);
-                backoff += 1;
+
+            /// Get the label of the symbol or None if the symbol is not in the symbol table
+            pub fn label(&self, symbol: &str) -> Option<Label> {
+                Ngrams::label(self, symbol)
+            }
+
+            /// Get the next state the model transitions to when starting in the provided state and
+            /// reading the symbol
+            /// A symbol not in the symbol table is read as `<unk>` if the model has it, otherwise
+            /// the next state is the initial state
+            pub fn get_next_state(&self, lm_state: LMState, symbol: &str) -> LMState {
+                self.next_state(lm_state, symbol)
             }
         }
-        // If the current state is associated with unigrams...
-        if lm_state.context_len == LMContext::One {
-            let backoff_penalty = backoff as f32 * BACKOFF_WEIGHT;
-            // .. read the information of all outgoing transitions from the bigram vec
-            for (label, log_prob, _, _) in self
-                .bigrams
-                .iter()
-                .skip(lm_state.ngrams_offset)
-                .take(lm_state.ngrams_no)
-            {
-                // Add the label and its likelihood to the HashMap storing the predictions
-                // The probabilities are added because they are the neg log probs
-                predictions
-                    .entry(*label)
-                    .or_insert(*log_prob + backoff_penalty);
+    };
+}
+
+impl_queries!(LanguageModel);
+impl_queries!(MappedLanguageModel);
+
+impl Ngrams for LanguageModel {
+    fn no_symbols(&self) -> usize {
+        self.symt.len()
+    }
+
+    fn symbol(&self, label: Label) -> &str {
+        &self.symt[label as usize]
+    }
+
+    fn label(&self, symbol: &str) -> Option<Label> {
+        self.symt.get_index_of(symbol).map(|label| label as Label)
+    }
+
+    fn sorted_label(&self, idx: usize) -> Label {
+        self.sorted_labels()[idx]
+    }
+
+    fn ranked_label(&self, idx: usize) -> Label {
+        self.ranked_labels()[idx]
+    }
+
+    fn order(&self) -> usize {
+        self.levels.len()
+    }
+
+    fn ngram(&self, level: usize, idx: usize) -> Ngram {
+        self.levels[level].ngrams[idx]
+    }
+
+    fn children(&self, level: usize, idx: usize) -> Range<usize> {
+        self.levels[level].children(idx)
+    }
+
+    fn stored_suffix(&self, level: usize, idx: usize) -> usize {
+        self.levels[level]
+            .suffixes
+            .get(idx)
+            .expect("Every n-gram of an order above two has a suffix")
+    }
+
+    fn ngram_backoff(&self, level: usize, idx: usize) -> Option<LogProb> {
+        self.levels[level].backoffs.get(idx).copied()
+    }
+
+    fn smoothing(&self) -> Smoothing {
+        self.smoothing
+    }
+}
+
+/// Read access to the symbols and n-grams of a language model
+/// The predictions and transitions are implemented on top of it, so models in memory and
+/// memory-mapped models give the same results
+trait Ngrams {
+    fn no_symbols(&self) -> usize;
+
+    fn symbol(&self, label: Label) -> &str;
+
+    fn label(&self, symbol: &str) -> Option<Label>;
+
+    /// Get the label at the index of the labels sorted by their symbol
+    fn sorted_label(&self, idx: usize) -> Label;
+    /// Get the label at the index of the labels sorted by their unigram probability from high to
+    /// low
+    fn ranked_label(&self, idx: usize) -> Label;
+
+    fn order(&self) -> usize;
+
+    /// Get the n-gram at the index of a level, the level of the unigrams is zero
+    fn ngram(&self, level: usize, idx: usize) -> Ngram;
+
+    /// Get the range of the children of an n-gram in the next level
+    fn children(&self, level: usize, idx: usize) -> Range<usize>;
+
+    /// Get the index of the suffix of an n-gram of order three or higher in the level below
+    fn stored_suffix(&self, level: usize, idx: usize) -> usize;
+
+    /// Get the backoff weight of an n-gram, if the model has backoff weights
+    fn ngram_backoff(&self, level: usize, idx: usize) -> Option<LogProb>;
+
+    fn smoothing(&self) -> Smoothing;
+
+    /// Get the index of the n-gram made of all but the first word of an n-gram in the level
+    /// below. The suffix of a unigram is the empty context
+    fn suffix(&self, level: usize, idx: usize) -> usize {
+        match level {
+            0 => 0,
+            1 => self.ngram(level, idx).0 as usize,
+            _ => self.stored_suffix(level, idx),
+        }
+    }
+
+    /// Get the predictions for the current state that start with the prefix
+    fn predictions(
+        &self,
+        lm_state: LMState,
+        prefix: &str,
+        max_no_predictions: usize,
+    ) -> Vec<(&str, LogProb)> {
+        let mut predictions = Vec::with_capacity(max_no_predictions);
+        self.label_predictions_into(lm_state, prefix, max_no_predictions, &mut predictions);
+        predictions
+            .into_iter()
+            .map(|(label, log_prob)| (self.symbol(label), log_prob))
+            .collect()
+    }
+
+    /// Write the labels of the predictions for the current state that start with the prefix
+    /// into the buffer, which is cleared first
+    ///
+    /// The buffer holds the candidates before the most probable ones are kept, so no memory is
+    /// allocated once it has grown large enough
+    fn label_predictions_into(
+        &self,
+        lm_state: LMState,
+        prefix: &str,
+        max_no_predictions: usize,
+        predictions: &mut Vec<Ngram>,
+    ) {
+        let start_state = self.enter_sentence(lm_state);
+        let mut lm_state = start_state;
+        let has_prefix = |label: Label| prefix.is_empty() || self.symbol(label).starts_with(prefix);
+        predictions.clear();
+        if max_no_predictions == 0 {
+            return;
+        }
+        let mut backoff_penalty = 0.0;
+
+        // As long as the current state has a context...
+        while lm_state.context_len > 0 {
+            // .. read the information of all outgoing transitions from the level of the next order
+            let level = lm_state.context_len;
+            for idx in lm_state.ngrams_offset..lm_state.ngrams_offset + lm_state.ngrams_no {
+                let (label, log_prob) = self.ngram(level, idx);
+                // The probabilities are added because they are the log probs
+                if has_prefix(label) && !self.is_predicted_before(start_state, level, label) {
+                    predictions.push((label, log_prob + backoff_penalty));
+                }
             }
-            // If not enough predictions were found, backoff and continue from the new state
-            if predictions.len() < max_no_predictions {
-                lm_state = self.backoff(lm_state);
-                backoff += 1;
+            backoff_penalty += self.backoff_weight(lm_state);
+            lm_state = self.backoff(lm_state);
+
+            // A word read after backing off has a probability of at most one, so its log prob is
+            // at most the penalty. If enough predictions beat that, no shorter context can
+            // change them and the model stops backing off
+            if predictions.len() >= max_no_predictions {
+                keep_most_probable(predictions, max_no_predictions);
+                if predictions[max_no_predictions - 1].1 > backoff_penalty {
+                    break;
+                }
             }
         }
 
         // If the current state is the initial state...
-        if lm_state.context_len == LMContext::Zero {
-            let backoff_penalty = backoff as f32 * BACKOFF_WEIGHT;
-            // .. read the information of all outgoing transitions from the unigram vec
-            for (idx, (log_prob, _, _)) in self.unigrams.iter().enumerate() {
-                // Add the label and its likelihood to the HashMap storing the predictions
-                // The probabilities are added because they are the neg log probs
-                predictions
-                    .entry(idx as u32)
-                    .or_insert(*log_prob + backoff_penalty);
+        if lm_state.context_len == 0 {
+            let unigram = |label: Label| (label, self.ngram(0, label as usize).1 + backoff_penalty);
+            if prefix.is_empty() {
+                // .. the best unigrams not predicted yet are the first ones of the labels
+                // ranked by their probability, so the rest of the vocabulary is never read
+                let max_len = predictions.len() + max_no_predictions;
+                for idx in 0..self.no_symbols() {
+                    if predictions.len() >= max_len {
+                        break;
+                    }
+                    let label = self.ranked_label(idx);
+                    if !self.is_predicted_before(start_state, 0, label) {
+                        predictions.push(unigram(label));
+                    }
+                }
+            } else {
+                // .. read the outgoing transitions of all symbols starting with the prefix from
+                // the unigrams
+                for idx in self.labels_with_prefix(prefix) {
+                    let label = self.sorted_label(idx);
+                    if !self.is_predicted_before(start_state, 0, label) {
+                        predictions.push(unigram(label));
+                    }
+                }
             }
         }
 
-        // Sort the predictions by their probability from high to low
-        let mut predictions = Vec::from_iter(predictions);
-        predictions.sort_by(|&(_, a), &(_, b)| b.partial_cmp(&a).unwrap_or(Ordering::Equal));
+        // Sort the most probable predictions by their probability from high to low. Ties are
+        // broken by the label
+        keep_most_probable(predictions, max_no_predictions);
+        predictions.sort_unstable_by(by_log_prob);
+    }
 
-        // Translate the labels into symbols
-        let mut final_predictions: Vec<(&str, f32)> = Vec::new();
-        let mut symbol;
-        for (label, log_prob) in predictions.iter().take(max_no_predictions) {
-            symbol = &self.symt[*label as usize];
-            final_predictions.push((symbol, *log_prob));
+    /// Lazily get the predictions of every word for the current state
+    fn label_predictions_iter(&self, lm_state: LMState) -> LabelPredictions<'_, Self>
+    where
+        Self: Sized,
+    {
+        let start_state = self.enter_sentence(lm_state);
+        LabelPredictions {
+            model: self,
+            start_state,
+            lm_state: start_state,
+            idx: 0,
+            backoff_penalty: 0.0,
+        }
+    }
+
+    /// Get the log probability stored for the n-gram of the symbols
+    /// The children of each n-gram are sorted by their label, so they are searched
+    fn stored_log_prob(&self, symbols: &[&str]) -> Option<LogProb> {
+        let (first, rest) = symbols.split_first()?;
+        if symbols.len() > self.order() {
+            return None;
+        }
+        let mut idx = self.label(first)? as usize;
+        for (level, symbol) in rest.iter().enumerate() {
+            let label = self.label(symbol)?;
+            idx = find_label(self.children(level, idx), label, |idx| {
+                self.ngram(level + 1, idx).0
+            })?;
         }
-        final_predictions
+        Some(self.ngram(rest.len(), idx).1)
+    }
+
+    /// Lazily get the symbols and log probabilities of all stored n-grams
+    fn stored_ngrams_iter(&self) -> StoredNgrams<'_, Self>
+    where
+        Self: Sized,
+    {
+        let mut ranges = Vec::with_capacity(self.order());
+        ranges.push(0..self.no_symbols());
+        StoredNgrams {
+            model: self,
+            ranges,
+            symbols: Vec::with_capacity(self.order()),
+        }
+    }
+
+    /// Check if the label is predicted by a context longer than the given length, which the
+    /// model reads before backing off to it from the start state
+    /// The children of each context are sorted by their label, so they are searched
+    fn is_predicted_before(&self, start_state: LMState, context_len: usize, label: Label) -> bool {
+        let mut lm_state = start_state;
+        while lm_state.context_len > context_len {
+            let children = lm_state.ngrams_offset..lm_state.ngrams_offset + lm_state.ngrams_no;
+            let level = lm_state.context_len;
+            if find_label(children, label, |idx| self.ngram(level, idx).0).is_some() {
+                return true;
+            }
+            lm_state = self.backoff(lm_state);
+        }
+        false
+    }
+
+    /// Get the indices of the sorted labels whose symbols start with the prefix
+    fn labels_with_prefix(&self, prefix: &str) -> Range<usize> {
+        // All symbols starting with the prefix follow the first symbol not smaller than it
+        let start = partition_point(0..self.no_symbols(), |idx| {
+            self.symbol(self.sorted_label(idx)) < prefix
+        });
+        let end = partition_point(start..self.no_symbols(), |idx| {
+            self.symbol(self.sorted_label(idx)).starts_with(prefix)
+        });
+        start..end
     }
 
     /// Get the next state the model transitions to when starting in the provided state and reading
     /// the symbol
-    pub fn get_next_state(&self, lm_state: LMState, symbol: &str) -> LMState {
-        let mut lm_state = lm_state;
+    fn next_state(&self, lm_state: LMState, symbol: &str) -> LMState {
+        let mut lm_state = self.enter_sentence(lm_state);
 
         // Try to translate the symbol into a label
-        // If we can't find the symbol, it is not a known word so the next state is the initial state
-        let label = match self.symt.get_index_of(symbol) {
+        // If we can't find the symbol and the model has no unknown symbol, it is not a known
+        // word so the next state is the initial state
+        let label = match self.label_or_unknown(symbol) {
             Some(known_label) => known_label,
             None => return LMState::default(),
         };
 
-        // If the current state is associated with a bigram..
-        if lm_state.context_len == LMContext::Two {
-            // check if an outgoing transition is possible with the provided label
-            // if there is one, return the destination state
-            if let Some(new_state) = self.try_finding_trigram_trs(label as u32, lm_state) {
+        // Check if an outgoing transition is possible with the provided label
+        // If there is none, backoff until there is one. Every known word has a unigram
+        loop {
+            if let Some(new_state) = self.try_finding_trs(label, lm_state) {
                 return new_state;
-            } else {
-                // Otherwise backoff
-                lm_state = self.backoff(lm_state);
             }
+            lm_state = self.backoff(lm_state);
         }
-        // If the current state is associated with a unigram..
-        if lm_state.context_len == LMContext::One {
-            // check if an outgoing transition is possible with the provided label
-            // if there is one, return the destination state
-            if let Some(new_state) = self.try_finding_bigram_trs(label as u32, lm_state) {
-                return new_state;
-            }
+    }
+
+    /// Get the label of the symbol or the label of the unknown symbol if the symbol is not in
+    /// the symbol table
+    fn label_or_unknown(&self, symbol: &str) -> Option<Label> {
+        self.label(symbol).or_else(|| self.label(UNKNOWN))
+    }
+
+    /// Read the sentence start symbol if the state is at the start of a sentence
+    fn enter_sentence(&self, lm_state: LMState) -> LMState {
+        if !lm_state.at_sentence_start {
+            return lm_state;
         }
-        // Return the transition from the initial state
-        self.finding_unigram_trs(label)
+        // Every known word has a unigram, so there is always a transition from the initial
+        // state
+        self.label(SENTENCE_START)
+            .and_then(|label| self.try_finding_trs(label, LMState::default()))
+            .unwrap_or_default()
     }
 
     /// Backoff to a state associated with suffix
     fn backoff(&self, start_state: LMState) -> LMState {
-        // Destructure the state
-        let LMState {
-            last_processed_label,
-            context_len,
-            ..
-        } = start_state;
-
-        match context_len {
+        match start_state.context_len {
             // It doesn't make sense to backoff when being in the start state. This should never happen, but it is also not an error
-            LMContext::Zero => start_state,
+            0 => start_state,
             // If we use only one word and backoff, we land in the start state of the language model
-            LMContext::One => LMState::default(),
-            // To backup from a context of two, we look up where to find the unigram, that represents the last processed label
-            LMContext::Two => {
-                let last_processed_label = last_processed_label as usize;
-                LMState {
-                    last_processed_label: last_processed_label as Label,
-                    ngrams_offset: self.unigrams[last_processed_label].1 as usize,
-                    ngrams_no: self.unigrams[last_processed_label as usize].2 as usize,
-                    context_len: LMContext::One,
-                }
+            1 => LMState::default(),
+            // To backoff from a longer context, we look up its suffix in the level below
+            context_len => {
+                let suffix_idx = self.suffix(context_len - 1, start_state.context_idx);
+                self.context_state(
+                    start_state.last_processed_label,
+                    context_len - 1,
+                    suffix_idx,
+                )
             }
         }
     }
 
-    fn try_finding_trigram_trs(&self, label: u32, lm_state: LMState) -> Option<LMState> {
-        let ngrams_offset = lm_state.ngrams_offset;
-        let ngrams_no = lm_state.ngrams_no;
+    /// Get the log weight that penalizes backing off from the state
+    fn backoff_weight(&self, lm_state: LMState) -> LogProb {
+        match self.smoothing() {
+            Smoothing::StupidBackoff => BACKOFF_WEIGHT,
+            Smoothing::Katz | Smoothing::KneserNey => match lm_state.context_len {
+                0 => 0.0,
+                context_len => self
+                    .ngram_backoff(context_len - 1, lm_state.context_idx)
+                    .unwrap_or(0.0),
+            },
+        }
+    }
+
+    /// Get the state after reading the label, if the context of the state has an n-gram ending
+    /// with it
+    fn try_finding_trs(&self, label: Label, lm_state: LMState) -> Option<LMState> {
+        let level = lm_state.context_len;
+        let idx = if level == 0 {
+            label as usize
+        } else {
+            let ngrams_offset = lm_state.ngrams_offset;
+            let ngrams_no = lm_state.ngrams_no;
+            find_label(ngrams_offset..ngrams_offset + ngrams_no, label, |idx| {
+                self.ngram(level, idx).0
+            })?
+        };
 
-        match self.trigrams[ngrams_offset..ngrams_offset + ngrams_no]
-            .binary_search_by_key(&(label as u32), |&(a, _, _)| a)
-        {
-            Ok(idx) => {
-                let offset_in_bigrams = self.trigrams[ngrams_offset + idx].2 as usize;
-                Some(LMState {
-                    last_processed_label: label,
-                    ngrams_offset: self.bigrams[offset_in_bigrams].2 as usize,
-                    ngrams_no: self.bigrams[offset_in_bigrams].3 as usize,
-                    context_len: LMContext::Two,
-                })
+        if level + 1 < self.order() {
+            // The n-gram becomes the context of the new state
+            Some(self.context_state(label, level + 1, idx))
+        } else {
+            // The n-grams of the highest order have no children, so the context of the new
+            // state is the suffix of the n-gram
+            Some(self.context_state(label, level, self.suffix(level, idx)))
+        }
+    }
+
+    /// Get the state whose context is the n-gram at the index of the level of its length
+    fn context_state(
+        &self,
+        last_processed_label: Label,
+        context_len: usize,
+        context_idx: usize,
+    ) -> LMState {
+        if context_len == 0 {
+            return LMState {
+                last_processed_label,
+                ..LMState::default()
+            };
+        }
+        let children = self.children(context_len - 1, context_idx);
+        LMState {
+            last_processed_label,
+            context_idx,
+            ngrams_offset: children.start,
+            ngrams_no: children.len(),
+            context_len,
+            at_sentence_start: false,
+        }
+    }
+}
+
+/// Lazily yields the prediction of every word for a state
+///
+/// The words following the context of the state are yielded first in the order of their label,
+/// followed by the other words following the shorter contexts the model backs off to. The
+/// remaining words are yielded by their unigram probability from high to low
+struct LabelPredictions<'a, M: Ngrams> {
+    model: &'a M,
+    start_state: LMState,
+    // The state whose children are yielded
+    lm_state: LMState,
+    // The index of the next child of the context of the state or of the next ranked label
+    idx: usize,
+    backoff_penalty: LogProb,
+}
+
+impl<M: Ngrams> Iterator for LabelPredictions<'_, M> {
+    type Item = Ngram;
+
+    fn next(&mut self) -> Option<Ngram> {
+        loop {
+            let level = self.lm_state.context_len;
+            let (label, log_prob) = if level == 0 {
+                if self.idx >= self.model.no_symbols() {
+                    return None;
+                }
+                let label = self.model.ranked_label(self.idx);
+                (label, self.model.ngram(0, label as usize).1)
+            } else if self.idx < self.lm_state.ngrams_no {
+                self.model
+                    .ngram(level, self.lm_state.ngrams_offset + self.idx)
+            } else {
+                // All children of the context were yielded, so backoff
+                self.backoff_penalty += self.model.backoff_weight(self.lm_state);
+                self.lm_state = self.model.backoff(self.lm_state);
+                self.idx = 0;
+                continue;
+            };
+            self.idx += 1;
+            if !self
+                .model
+                .is_predicted_before(self.start_state, level, label)
+            {
+                return Some((label, log_prob + self.backoff_penalty));
             }
-            Err(_) => None,
         }
     }
+}
 
-    fn try_finding_bigram_trs(&self, label: u32, lm_state: LMState) -> Option<LMState> {
-        let ngrams_offset = lm_state.ngrams_offset;
-        let ngrams_no = lm_state.ngrams_no;
+/// Lazily yields the symbols and log probability of every stored n-gram
+///
+/// The n-grams are visited depth first, so each n-gram is followed by its children and all
+/// n-grams are yielded in the order of their labels
+struct StoredNgrams<'a, M: Ngrams> {
+    model: &'a M,
+    // The range of the n-grams left to visit in each level of the current path
+    ranges: Vec<Range<usize>>,
+    // The symbols of the last n-gram yielded
+    symbols: Vec<&'a str>,
+}
 
-        match self.bigrams[ngrams_offset..ngrams_offset + ngrams_no]
-            .binary_search_by_key(&(label as u32), |&(a, _, _, _)| a)
-        {
-            Ok(idx) => Some(LMState {
-                last_processed_label: label,
-                ngrams_offset: self.bigrams[ngrams_offset + idx].2 as usize,
-                ngrams_no: self.bigrams[ngrams_offset + idx].3 as usize,
-                context_len: LMContext::Two,
-            }),
-            Err(_) => None,
+impl<'a, M: Ngrams> Iterator for StoredNgrams<'a, M> {
+    type Item = (Vec<&'a str>, LogProb);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let level = self.ranges.len().checked_sub(1)?;
+            let idx = match self.ranges[level].next() {
+                Some(idx) => idx,
+                None => {
+                    self.ranges.pop();
+                    continue;
+                }
+            };
+            let (label, log_prob) = self.model.ngram(level, idx);
+            self.symbols.truncate(level);
+            self.symbols.push(self.model.symbol(label));
+            if level + 1 < self.model.order() {
+                self.ranges.push(self.model.children(level, idx));
+            }
+            return Some((self.symbols.clone(), log_prob));
         }
     }
+}
 
-    fn finding_unigram_trs(&self, label: usize) -> LMState {
-        LMState {
-            last_processed_label: label as u32,
-            ngrams_offset: self.unigrams[label].1 as usize,
-            ngrams_no: self.unigrams[label].2 as usize,
-            context_len: LMContext::One,
+/// Keep the most probable predictions, the least probable of them is the last one
+fn keep_most_probable(predictions: &mut Vec<Ngram>, max_no_predictions: usize) {
+    if predictions.len() >= max_no_predictions && max_no_predictions > 0 {
+        predictions.select_nth_unstable_by(max_no_predictions - 1, by_log_prob);
+    }
+    predictions.truncate(max_no_predictions);
+}
+
+/// Order n-grams by their probability from high to low and n-grams with the same probability by
+/// their label
+fn by_log_prob(a: &Ngram, b: &Ngram) -> Ordering {
+    b.1.total_cmp(&a.1).then(a.0.cmp(&b.0))
+}
+
+/// Get the first index of the range for which the predicate is false
+/// The predicate has to be true for all indices before it and false for all indices after it
+fn partition_point(range: Range<usize>, pred: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (range.start, range.end);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if pred(mid) {
+            low = mid + 1;
+        } else {
+            high = mid;
         }
     }
+    low
 }
 
+/// Get the index of the n-gram with the label in the range of n-grams sorted by their label
+fn find_label(
+    range: Range<usize>,
+    label: Label,
+    label_at: impl Fn(usize) -> Label,
+) -> Option<usize> {
+    let idx = partition_point(range.clone(), |idx| label_at(idx) < label);
+    (idx < range.end && label_at(idx) == label).then_some(idx)
+}
+
+/// Convert the text files of the folder `ngrams_test/` in test mode or `./ngrams/` otherwise
+/// into the file `language_model.bin` of the same folder
+#[deprecated(note = "use `LanguageModel::convert` with `ConvertConfig::from_text_folder` instead")]
 pub fn convert_text_to_cmprssd_bin(test_mode: bool) -> Result<(), Box<bincode::ErrorKind>> {
     let folder = if test_mode {
         "ngrams_test/"
     } else {
         "./ngrams/"
     };
-    let fname_symt = format!("{}symt.txt", folder);
-    let fname_unigrams = format!("{}{}gms.txt", folder, 1);
-    let fname_bigrams = format!("{}{}gms.txt", folder, 2);
-    let fname_trigrams = format!("{}{}gms.txt", folder, 3);
-    let fname_write_bin = format!("{}language_model.bin", folder);
-
-    println!("Test mode: {}", test_mode);
-    println!("Reading ngrams from the files:");
-    println!("symt     : {}", fname_symt);
-    println!("unigrams : {}", fname_unigrams);
-    println!("bigrams  : {}", fname_bigrams);
-    println!("trigrams : {}", fname_trigrams);
-    println!();
-
-    let language_model = LanguageModel::read_from_text(
-        &fname_symt,
-        &fname_unigrams,
-        &fname_bigrams,
-        &fname_trigrams,
-    );
-
-    println!("Language model successfully read from file");
-    language_model.write(&fname_write_bin)?;
-    println!("Language model successfully written to file");
-    Ok(())
+    let output = format!("{}language_model.bin", folder);
+    match LanguageModel::convert(ConvertConfig::from_text_folder(folder, &output)) {
+        Ok(_) => Ok(()),
+        Err(Error::Io(error)) => Err(Box::new(bincode::ErrorKind::Io(error))),
+        Err(Error::Serialization(error)) => Err(error),
+        Err(error) => Err(Box::new(bincode::ErrorKind::Custom(error.to_string()))),
+    }
 }
@@ -1,42 +1,280 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    process::ExitCode,
+};
+
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 use language_model::*;
 
-fn main() {
-    //convert_text_to_cmprssd_bin(false).unwrap();
-    println!("Loading language model from file...");
-    let fname_language_model = "./ngrams/language_model.bin";
-    let language_model = LanguageModel::read(fname_language_model).unwrap();
-    println!("Done loading");
-    println!();
+mod repl;
+use repl::Repl;
 
-    let entered_text = vec!["hello", "how", "are", "you"];
-    print_predicions(entered_text, &language_model);
+/// Convert, inspect and query n-gram language models
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
 
-    println!();
-    println!();
+#[derive(Subcommand)]
+enum Command {
+    /// Convert text n-gram files, an ARPA file or a binary file of an older version into a
+    /// binary model
+    Convert(ConvertArgs),
+    /// Print the most probable next words after reading some words
+    Predict(PredictArgs),
+    /// Read words from stdin and print the predictions after each of them
+    Interactive(InteractiveArgs),
+    /// Score each line of a text as a sentence and print the perplexity
+    Score(ScoreArgs),
+    /// Print the size of the vocabulary, the number of n-grams, the memory used and the
+    /// distribution of the log probabilities of a model
+    Stats(ModelArgs),
+    /// Write a model in another format
+    Export(ExportArgs),
+    /// Check the structure of a model
+    Validate(ModelArgs),
+}
 
-    let entered_text = vec!["the", "man", "walked", "down", "the", "street"];
-    print_predicions(entered_text, &language_model);
+#[derive(Args)]
+#[command(group(ArgGroup::new("input").required(true).args(["text", "arpa", "binary"])))]
+struct ConvertArgs {
+    /// The symbol table and the files of the unigrams, bigrams and trigrams
+    #[arg(long, num_args = 4, value_names = ["SYMT", "UNIGRAMS", "BIGRAMS", "TRIGRAMS"])]
+    text: Option<Vec<String>>,
+    /// An ARPA file
+    #[arg(long, value_name = "FILE")]
+    arpa: Option<String>,
+    /// A binary model, which may have been written by an older version
+    #[arg(long, value_name = "FILE")]
+    binary: Option<String>,
+    /// The file the binary model is written to
+    #[arg(short, long, value_name = "FILE")]
+    output: String,
+    /// Skip the lines of the text files that can not be parsed
+    #[arg(long, requires = "text")]
+    lenient: bool,
+    /// Validate the structure of the model before writing it
+    #[arg(long)]
+    validate: bool,
+    /// The level of the compression from 0 for none to 9 for the best one
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(0..=9))]
+    compression: u32,
 }
 
-fn print_predicions(entered_text: Vec<&str>, language_model: &LanguageModel) {
-    println!("Entered text:");
-    println!("{:?}", entered_text);
-    println!();
-    let mut entered_text = entered_text.iter();
-    let mut lm_state = LMState::default();
-
-    for no_prediction in 0..=entered_text.len() {
-        println!("Current state {:?}", lm_state);
-        println!();
-        let predictions = language_model.predict(lm_state, 10);
-        println!("Prediction after reading {} input symbols", no_prediction);
-        println!("{:?}", predictions);
-        println!();
-
-        if let Some(symbol) = entered_text.next() {
-            lm_state = language_model.get_next_state(lm_state, symbol);
-            println!("Entered symbol {}", symbol);
-            println!();
+#[derive(Args)]
+struct ModelArgs {
+    /// The model, a binary file or an ARPA file if it ends with .arpa
+    model: String,
+}
+
+#[derive(Args)]
+struct PredictArgs {
+    #[command(flatten)]
+    model: ModelArgs,
+    /// The words read before predicting
+    words: Vec<String>,
+    /// The number of predictions
+    #[arg(short, default_value_t = 10)]
+    k: usize,
+    /// Only predict words starting with the prefix
+    #[arg(long, default_value = "")]
+    prefix: String,
+    /// Start at the beginning of a sentence instead of without context
+    #[arg(long)]
+    sentence: bool,
+}
+
+#[derive(Args)]
+struct InteractiveArgs {
+    #[command(flatten)]
+    model: ModelArgs,
+    /// The number of predictions, it can be changed with :k
+    #[arg(short, default_value_t = 10)]
+    k: usize,
+    /// Start at the beginning of a sentence instead of without context
+    #[arg(long)]
+    sentence: bool,
+}
+
+#[derive(Args)]
+struct ScoreArgs {
+    #[command(flatten)]
+    model: ModelArgs,
+    /// The text to score, one sentence per line. It is read from stdin if it is missing
+    input: Option<String>,
+    /// Score the lines without the start and end of the sentences
+    #[arg(long)]
+    no_boundaries: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// The ARPA text format
+    Arpa,
+    /// The uncompressed layout of the memory-mapped model
+    Mapped,
+    /// The compressed binary format of the current version
+    Binary,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    #[command(flatten)]
+    model: ModelArgs,
+    /// The file the model is written to
+    #[arg(short, long, value_name = "FILE")]
+    output: String,
+    /// The format of the written file
+    #[arg(long, value_enum, default_value_t = ExportFormat::Arpa)]
+    format: ExportFormat,
+}
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Convert(args) => convert(args),
+        Command::Predict(args) => predict(args),
+        Command::Interactive(args) => interactive(args),
+        Command::Score(args) => score(args),
+        Command::Stats(args) => stats(args),
+        Command::Export(args) => export(args),
+        Command::Validate(args) => validate(args),
+    };
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Read a binary model or an ARPA file, which is recognized by its extension
+fn read_model(fname: &str) -> Result<LanguageModel, Error> {
+    if fname.ends_with(".arpa") {
+        LanguageModel::read_arpa(fname)
+    } else {
+        LanguageModel::read(fname)
+    }
+}
+
+fn convert(args: ConvertArgs) -> Result<ExitCode, Error> {
+    let input = if let Some(fnames) = args.text {
+        let [symt, unigrams, bigrams, trigrams]: [String; 4] =
+            fnames.try_into().expect("clap requires four text files");
+        ConvertInput::Text {
+            symt,
+            unigrams,
+            bigrams,
+            trigrams,
         }
+    } else if let Some(fname) = args.arpa {
+        ConvertInput::Arpa(fname)
+    } else if let Some(fname) = args.binary {
+        ConvertInput::Binary(fname)
+    } else {
+        unreachable!("clap requires one of the inputs")
+    };
+
+    let mut config = ConvertConfig::new(input, &args.output);
+    if args.lenient {
+        config.mode = ParseMode::Lenient;
+    }
+    config.compression_level = args.compression;
+    config.validate = args.validate;
+    let report = LanguageModel::convert(config)?;
+    for warning in &report.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn predict(args: PredictArgs) -> Result<ExitCode, Error> {
+    let language_model = read_model(&args.model.model)?;
+    let start_state = if args.sentence {
+        LMState::sentence_start()
+    } else {
+        LMState::default()
+    };
+    let lm_state = args.words.iter().fold(start_state, |lm_state, word| {
+        language_model.get_next_state(lm_state, word)
+    });
+    for (symbol, log_prob) in language_model.predict_with_prefix(lm_state, &args.prefix, args.k) {
+        println!("{}\t{:.6}", symbol, log_prob);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn interactive(args: InteractiveArgs) -> Result<ExitCode, Error> {
+    let language_model = read_model(&args.model.model)?;
+    Repl::new(&language_model, args.k, args.sentence).run(io::stdin().lock(), io::stdout())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn score(args: ScoreArgs) -> Result<ExitCode, Error> {
+    let language_model = read_model(&args.model.model)?;
+    let input: Box<dyn BufRead> = match &args.input {
+        Some(fname) => Box::new(BufReader::new(File::open(fname)?)),
+        None => Box::new(io::stdin().lock()),
+    };
+
+    // Each line is printed with its log probability, the perplexity is computed over all words
+    // that were scored
+    let mut corpus_score = CorpusScore::default();
+    for line in input.lines() {
+        let line = line?;
+        let symbols: Vec<&str> = line.split_whitespace().collect();
+        let score = if args.no_boundaries {
+            language_model.score_sequence(&symbols)
+        } else {
+            language_model.score_sentence(&symbols)
+        };
+        println!("{:.6}\t{}", score.log_prob, line);
+        corpus_score.add(&score);
+    }
+    println!();
+    println!("Sentences: {}", corpus_score.no_sequences);
+    println!("Scored words: {}", corpus_score.no_scored);
+    println!("Unknown words: {}", corpus_score.no_oovs);
+    println!("Log probability: {:.6}", corpus_score.log_prob);
+    println!("Perplexity: {:.6}", corpus_score.perplexity());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn stats(args: ModelArgs) -> Result<ExitCode, Error> {
+    // Only binary files of the current version have a header
+    if let Ok(header) = LanguageModel::read_header(&args.model) {
+        println!("Format version: {}", header.version);
+        println!("Written by: {}", header.creator);
+        println!("Written at: {} s since the Unix epoch", header.created);
+    }
+    let language_model = read_model(&args.model)?;
+    print!("{}", language_model.stats());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn export(args: ExportArgs) -> Result<ExitCode, Error> {
+    let language_model = read_model(&args.model.model)?;
+    match args.format {
+        ExportFormat::Arpa => language_model.write_arpa(&args.output)?,
+        ExportFormat::Mapped => language_model.write_mapped(&args.output)?,
+        ExportFormat::Binary => language_model.write(&args.output)?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn validate(args: ModelArgs) -> Result<ExitCode, Error> {
+    let language_model = read_model(&args.model)?;
+    let violations = language_model.validate();
+    if violations.is_empty() {
+        println!("The model is valid");
+        return Ok(ExitCode::SUCCESS);
+    }
+    for violation in &violations {
+        println!("{}", violation);
     }
+    println!("Found {} violations", violations.len());
+    Ok(ExitCode::FAILURE)
 }
@@ -0,0 +1,441 @@
+use std::{
+    io::{self, BufWriter, Write},
+    str,
+};
+
+use memmap2::Mmap;
+
+use super::*;
+
+// The file starts with a header, which is followed by the sections holding the n-grams of each
+// order, the offsets of their children, their suffixes and backoff weights and the symbol table.
+// All numbers are stored little endian and every section starts at a multiple of the alignment
+const MAGIC: &[u8; 8] = b"LMMAPPED";
+const VERSION: u32 = 4;
+// The header ends with the number of n-grams of each order
+const HEADER_LEN: usize = 48;
+const ALIGNMENT: usize = 8;
+
+// Size of the records of the sections in bytes. The offsets and suffixes are indices, which take
+// four bytes unless a level has too many n-grams
+const NGRAM_LEN: usize = 8; // label, log prob
+const NARROW_INDEX_LEN: usize = 4;
+const WIDE_INDEX_LEN: usize = 8;
+const BACKOFF_LEN: usize = 4;
+const SYMBOL_OFFSET_LEN: usize = 8;
+const LABEL_LEN: usize = 4;
+
+/// The position of the sections of one order in a memory-mapped file
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct LevelLayout {
+    ngrams: Range<usize>,
+    // The offset of the children of each n-gram followed by the end of the children of the last
+    // one. Empty for the highest order
+    offsets: Range<usize>,
+    // Empty for unigrams and bigrams
+    suffixes: Range<usize>,
+    // Empty if the model has no backoff weights or for the highest order
+    backoffs: Range<usize>,
+}
+
+/// The position of the sections in a memory-mapped file
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Layout {
+    levels: Vec<LevelLayout>,
+    index_len: usize,
+    // The start of each symbol in the symbol data followed by the end of the last one
+    symbol_offsets: Range<usize>,
+    // The labels sorted by their symbol
+    sorted_labels: Range<usize>,
+    // The labels sorted by their unigram probability from high to low
+    ranked_labels: Range<usize>,
+    symbols: Range<usize>,
+    len: usize,
+}
+
+impl Layout {
+    /// Compute the layout of a file from the number of n-grams of each order
+    /// The number of symbols is the number of unigrams
+    /// Returns None if the file would be larger than the address space
+    fn new(
+        no_ngrams: &[usize],
+        has_backoffs: bool,
+        index_len: usize,
+        symbols_len: usize,
+    ) -> Option<Self> {
+        let no_symbols = *no_ngrams.first()?;
+        let mut end = no_ngrams.len().checked_mul(8)?.checked_add(HEADER_LEN)?;
+        let mut section = |no: usize, record_len: usize| -> Option<Range<usize>> {
+            let start = end.checked_next_multiple_of(ALIGNMENT)?;
+            end = start.checked_add(no.checked_mul(record_len)?)?;
+            Some(start..end)
+        };
+
+        let order = no_ngrams.len();
+        let mut levels = Vec::with_capacity(order);
+        for (level, &no) in no_ngrams.iter().enumerate() {
+            let no_offsets = if level + 1 < order {
+                no.checked_add(1)?
+            } else {
+                0
+            };
+            let no_suffixes = if level >= 2 { no } else { 0 };
+            let no_backoffs = if has_backoffs && level + 1 < order {
+                no
+            } else {
+                0
+            };
+            levels.push(LevelLayout {
+                ngrams: section(no, NGRAM_LEN)?,
+                offsets: section(no_offsets, index_len)?,
+                suffixes: section(no_suffixes, index_len)?,
+                backoffs: section(no_backoffs, BACKOFF_LEN)?,
+            });
+        }
+        let symbol_offsets = section(no_symbols.checked_add(1)?, SYMBOL_OFFSET_LEN)?;
+        let sorted_labels = section(no_symbols, LABEL_LEN)?;
+        let ranked_labels = section(no_symbols, LABEL_LEN)?;
+        let symbols = section(symbols_len, 1)?;
+        Some(Self {
+            levels,
+            index_len,
+            symbol_offsets,
+            sorted_labels,
+            ranked_labels,
+            len: symbols.end,
+            symbols,
+        })
+    }
+}
+
+/// A language model that is served directly from a memory-mapped file
+///
+/// Opening the model does not deserialize the n-grams, they are read from the file when they are
+/// needed. The predictions and transitions are the same as the ones of the `LanguageModel` the
+/// file was written from
+#[derive(Debug)]
+pub struct MappedLanguageModel {
+    mmap: Mmap,
+    layout: Layout,
+    no_symbols: usize,
+    smoothing: Smoothing,
+}
+
+impl MappedLanguageModel {
+    /// Memory-map a file written by `LanguageModel::write_mapped`
+    ///
+    /// Only the header and the symbol table are checked, so a corrupted file can cause a panic
+    /// later on. The file must not be modified while the model is in use
+    pub fn open(fname: &str) -> Result<Self, Error> {
+        let format_error = |message: String| Error::Format {
+            fname: fname.to_string(),
+            message,
+        };
+
+        let file = File::open(fname)?;
+        // Safety: All reads are bounds checked, but the file must not be changed while it is
+        // mapped, which is documented above
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        if mmap.len() < HEADER_LEN || &mmap[..MAGIC.len()] != MAGIC {
+            return Err(format_error(
+                "The file is not a memory-mapped language model".to_string(),
+            ));
+        }
+        let version = read_u32(&mmap, 8);
+        if version != VERSION {
+            return Err(format_error(format!(
+                "The version {} of the file is not supported, it has to be written again with \
+                 LanguageModel::write_mapped",
+                version
+            )));
+        }
+        let smoothing = match read_u32(&mmap, 12) {
+            0 => Smoothing::StupidBackoff,
+            1 => Smoothing::Katz,
+            2 => Smoothing::KneserNey,
+            smoothing => {
+                return Err(format_error(format!(
+                    "The smoothing {} is not known",
+                    smoothing
+                )))
+            }
+        };
+        let count = |pos: usize| {
+            let bytes = mmap.get(pos..pos + 8)?;
+            usize::try_from(read_u64(bytes, 0)).ok()
+        };
+        let has_backoffs = read_u64(&mmap, 24) != 0;
+        let index_len = count(40)
+            .filter(|&index_len| index_len == NARROW_INDEX_LEN || index_len == WIDE_INDEX_LEN)
+            .ok_or_else(|| format_error("The size of the indices is not valid".to_string()))?;
+        let layout = count(16)
+            .and_then(|order| {
+                let no_ngrams = (0..order)
+                    .map(|level| count(HEADER_LEN + 8 * level))
+                    .collect::<Option<Vec<usize>>>()?;
+                Layout::new(&no_ngrams, has_backoffs, index_len, count(32)?)
+            })
+            .filter(|layout| layout.len == mmap.len())
+            .ok_or_else(|| {
+                format_error("The size of the file does not match its header".to_string())
+            })?;
+        let no_symbols = layout.levels[0].ngrams.len() / NGRAM_LEN;
+
+        let language_model = Self {
+            mmap,
+            layout,
+            no_symbols,
+            smoothing,
+        };
+        language_model.check_symbols().map_err(format_error)?;
+        Ok(language_model)
+    }
+
+    /// Check that the symbols are valid UTF-8 and the sorted and ranked labels are in the symbol
+    /// table, so looking them up can not fail
+    fn check_symbols(&self) -> Result<(), String> {
+        let symbols = &self.mmap[self.layout.symbols.clone()];
+        let mut start = 0;
+        for idx in 0..=self.no_symbols {
+            let end = read_u64(
+                self.record(&self.layout.symbol_offsets, SYMBOL_OFFSET_LEN, idx),
+                0,
+            );
+            let end = usize::try_from(end).unwrap_or(usize::MAX);
+            if idx == 0 && end != 0 || end < start || end > symbols.len() {
+                return Err(format!("The offset of symbol {} is invalid", idx));
+            }
+            if str::from_utf8(&symbols[start..end]).is_err() {
+                return Err(format!("The symbol {} is not valid UTF-8", idx - 1));
+            }
+            start = end;
+        }
+        for idx in 0..self.no_symbols {
+            if self.sorted_label(idx) as usize >= self.no_symbols {
+                return Err(format!(
+                    "The sorted label {} is not in the symbol table",
+                    idx
+                ));
+            }
+            if self.ranked_label(idx) as usize >= self.no_symbols {
+                return Err(format!(
+                    "The ranked label {} is not in the symbol table",
+                    idx
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the bytes of the record at the index of a section
+    fn record(&self, section: &Range<usize>, record_len: usize, idx: usize) -> &[u8] {
+        &self.mmap[section.clone()][idx * record_len..(idx + 1) * record_len]
+    }
+
+    /// Get the index at the position of a section of offsets or suffixes
+    fn index(&self, section: &Range<usize>, idx: usize) -> usize {
+        let record = self.record(section, self.layout.index_len, idx);
+        if self.layout.index_len == WIDE_INDEX_LEN {
+            read_u64(record, 0) as usize
+        } else {
+            read_u32(record, 0) as usize
+        }
+    }
+}
+
+impl Ngrams for MappedLanguageModel {
+    fn no_symbols(&self) -> usize {
+        self.no_symbols
+    }
+
+    fn symbol(&self, label: Label) -> &str {
+        let offsets = &self.layout.symbol_offsets;
+        let start = read_u64(self.record(offsets, SYMBOL_OFFSET_LEN, label as usize), 0);
+        let end = read_u64(
+            self.record(offsets, SYMBOL_OFFSET_LEN, label as usize + 1),
+            0,
+        );
+        let bytes = &self.mmap[self.layout.symbols.clone()][start as usize..end as usize];
+        str::from_utf8(bytes).expect("The symbols are checked when opening the file")
+    }
+
+    fn label(&self, symbol: &str) -> Option<Label> {
+        let idx = partition_point(0..self.no_symbols, |idx| {
+            Ngrams::symbol(self, self.sorted_label(idx)) < symbol
+        });
+        let label = self.sorted_label(idx.min(self.no_symbols.checked_sub(1)?));
+        (Ngrams::symbol(self, label) == symbol).then_some(label)
+    }
+
+    fn sorted_label(&self, idx: usize) -> Label {
+        read_u32(self.record(&self.layout.sorted_labels, LABEL_LEN, idx), 0)
+    }
+
+    fn ranked_label(&self, idx: usize) -> Label {
+        read_u32(self.record(&self.layout.ranked_labels, LABEL_LEN, idx), 0)
+    }
+
+    fn order(&self) -> usize {
+        self.layout.levels.len()
+    }
+
+    fn ngram(&self, level: usize, idx: usize) -> Ngram {
+        let record = self.record(&self.layout.levels[level].ngrams, NGRAM_LEN, idx);
+        (read_u32(record, 0), read_f32(record, 4))
+    }
+
+    fn children(&self, level: usize, idx: usize) -> Range<usize> {
+        let offsets = &self.layout.levels[level].offsets;
+        if offsets.is_empty() {
+            return 0..0;
+        }
+        self.index(offsets, idx)..self.index(offsets, idx + 1)
+    }
+
+    fn stored_suffix(&self, level: usize, idx: usize) -> usize {
+        self.index(&self.layout.levels[level].suffixes, idx)
+    }
+
+    fn ngram_backoff(&self, level: usize, idx: usize) -> Option<LogProb> {
+        let backoffs = &self.layout.levels[level].backoffs;
+        if backoffs.is_empty() {
+            return None;
+        }
+        Some(read_f32(self.record(backoffs, BACKOFF_LEN, idx), 0))
+    }
+
+    fn smoothing(&self) -> Smoothing {
+        self.smoothing
+    }
+}
+
+impl LanguageModel {
+    /// Write the language model uncompressed in the layout used by `MappedLanguageModel`
+    /// The model is validated first, because the mapped model relies on its structure
+    pub fn write_mapped(&self, fname: &str) -> Result<(), Error> {
+        self.check()?;
+
+        let has_backoffs = self.levels.iter().any(|level| !level.backoffs.is_empty());
+        let no_ngrams: Vec<usize> = self.levels.iter().map(|level| level.ngrams.len()).collect();
+        let symbols_len = self.symt.iter().map(String::len).sum();
+        // An offset can be the number of n-grams of a level, a suffix is always less
+        let index_len = if no_ngrams.iter().all(|&no| u32::try_from(no).is_ok()) {
+            NARROW_INDEX_LEN
+        } else {
+            WIDE_INDEX_LEN
+        };
+        let layout =
+            Layout::new(&no_ngrams, has_backoffs, index_len, symbols_len).ok_or_else(|| {
+                Error::Structure("The language model is empty or too large".to_string())
+            })?;
+
+        let mut writer = SectionWriter {
+            writer: BufWriter::new(File::create(fname)?),
+            pos: 0,
+        };
+        writer.write(MAGIC)?;
+        writer.write(&VERSION.to_le_bytes())?;
+        let smoothing: u32 = match self.smoothing {
+            Smoothing::StupidBackoff => 0,
+            Smoothing::Katz => 1,
+            Smoothing::KneserNey => 2,
+        };
+        writer.write(&smoothing.to_le_bytes())?;
+        for count in [
+            self.levels.len(),
+            has_backoffs as usize,
+            symbols_len,
+            index_len,
+        ]
+        .iter()
+        .chain(&no_ngrams)
+        {
+            writer.write(&(*count as u64).to_le_bytes())?;
+        }
+
+        for (level, level_layout) in self.levels.iter().zip(&layout.levels) {
+            writer.start(&level_layout.ngrams)?;
+            for &(label, log_prob) in &level.ngrams {
+                writer.write(&label.to_le_bytes())?;
+                writer.write(&log_prob.to_le_bytes())?;
+            }
+            writer.start(&level_layout.offsets)?;
+            for offset in level.offsets.iter() {
+                writer.write_index(offset, index_len)?;
+            }
+            writer.start(&level_layout.suffixes)?;
+            for suffix in level.suffixes.iter() {
+                writer.write_index(suffix, index_len)?;
+            }
+            writer.start(&level_layout.backoffs)?;
+            for backoff in &level.backoffs {
+                writer.write(&backoff.to_le_bytes())?;
+            }
+        }
+        writer.start(&layout.symbol_offsets)?;
+        let mut symbol_offset = 0;
+        writer.write(&0u64.to_le_bytes())?;
+        for symbol in &self.symt {
+            symbol_offset += symbol.len() as u64;
+            writer.write(&symbol_offset.to_le_bytes())?;
+        }
+        writer.start(&layout.sorted_labels)?;
+        for label in self.sorted_labels() {
+            writer.write(&label.to_le_bytes())?;
+        }
+        writer.start(&layout.ranked_labels)?;
+        for label in self.ranked_labels() {
+            writer.write(&label.to_le_bytes())?;
+        }
+        writer.start(&layout.symbols)?;
+        for symbol in &self.symt {
+            writer.write(symbol.as_bytes())?;
+        }
+        debug_assert_eq!(writer.pos, layout.len);
+        writer.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes the sections of a file and keeps track of the position to align them
+struct SectionWriter<W: Write> {
+    writer: W,
+    pos: usize,
+}
+
+impl<W: Write> SectionWriter<W> {
+    /// Pad the file with zeros up to the start of the section
+    fn start(&mut self, section: &Range<usize>) -> io::Result<()> {
+        let padding = [0; ALIGNMENT];
+        self.write(&padding[..section.start - self.pos])
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.pos += bytes.len();
+        Ok(())
+    }
+
+    fn write_index(&mut self, idx: usize, index_len: usize) -> io::Result<()> {
+        if index_len == WIDE_INDEX_LEN {
+            self.write(&(idx as u64).to_le_bytes())
+        } else {
+            self.write(&(idx as u32).to_le_bytes())
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+}
+
+fn read_u64(bytes: &[u8], pos: usize) -> u64 {
+    let mut le_bytes = [0; 8];
+    le_bytes.copy_from_slice(&bytes[pos..pos + 8]);
+    u64::from_le_bytes(le_bytes)
+}
+
+fn read_f32(bytes: &[u8], pos: usize) -> f32 {
+    f32::from_bits(read_u32(bytes, pos))
+}
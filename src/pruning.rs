@@ -0,0 +1,246 @@
+use std::collections::HashSet;
+
+use super::{builder::NgramEntry, *};
+
+/// The size of a language model before and after pruning it or restricting its vocabulary
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PruneReport {
+    /// The number of n-grams of each order before pruning, starting with the unigrams
+    pub ngrams_before: Vec<usize>,
+    /// The number of n-grams of each order after pruning, starting with the unigrams
+    pub ngrams_after: Vec<usize>,
+    /// The number of bytes used by the n-grams and the symbol table before pruning
+    pub bytes_before: usize,
+    /// The number of bytes used by the n-grams and the symbol table after pruning
+    pub bytes_after: usize,
+}
+
+impl PruneReport {
+    /// Get the number of n-grams that were removed
+    pub fn no_removed(&self) -> usize {
+        self.ngrams_before.iter().sum::<usize>() - self.ngrams_after.iter().sum::<usize>()
+    }
+}
+
+impl LanguageModel {
+    /// Remove the n-grams that can be estimated well by backing off with Stolcke's relative
+    /// entropy pruning
+    ///
+    /// An n-gram is removed if replacing its probability with the backoff estimate increases
+    /// the perplexity of the model by less than the threshold relative to the perplexity, for
+    /// example 1e-7. Unigrams and n-grams that are the context or suffix of a longer n-gram that
+    /// is kept are never removed. The backoff weights of the contexts that lost n-grams are
+    /// recomputed for Katz and Kneser-Ney smoothing, so their distributions stay normalized.
+    /// `prune_by_count` prunes by the counts of the n-grams instead
+    pub fn prune(&mut self, threshold: f64) -> Result<PruneReport, Error> {
+        let ngrams = self.to_ngrams();
+        let log_probs: Vec<HashMap<&[Label], f64>> = ngrams
+            .iter()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|(words, log_prob, _)| (&words[..], *log_prob as f64))
+                    .collect()
+            })
+            .collect();
+        // The suffix of every n-gram is stored, so the backoff estimate of an n-gram is the
+        // probability of its suffix weighted by the backoff weight of its context
+        let log_prob = |words: &[Label]| log_probs[words.len() - 1][words];
+
+        let mut prunable: Vec<Vec<bool>> = vec![vec![false; ngrams[0].len()]];
+        for entries in &ngrams[1..] {
+            let context_sums = context_sums(entries, &log_prob);
+            let level_prunable = entries.iter().map(|(words, _, _)| {
+                let context = &words[..words.len() - 1];
+                let context_log_prob: f64 = (1..=context.len())
+                    .map(|len| log_prob(&context[..len]))
+                    .sum();
+                let prob = log_prob(words).exp();
+                let shorter_prob = log_prob(&words[1..]).exp();
+                let (seen, seen_shorter) = context_sums[context];
+                let delta = match self.smoothing {
+                    Smoothing::StupidBackoff => {
+                        prob * (shorter_prob.ln() + BACKOFF_WEIGHT as f64 - prob.ln())
+                    }
+                    Smoothing::Katz | Smoothing::KneserNey => {
+                        // The backoff weight distributes the probability left by the seen words
+                        // over the other words. Removing the n-gram adds it to both
+                        let (left, left_shorter) = (1.0 - seen, 1.0 - seen_shorter);
+                        let pruned_backoff = ((left + prob) / (left_shorter + shorter_prob)).ln();
+                        let mut delta = prob * (shorter_prob.ln() + pruned_backoff - prob.ln());
+                        if left > 0.0 && left_shorter > 0.0 {
+                            delta += left * (pruned_backoff - (left / left_shorter).ln());
+                        }
+                        delta
+                    }
+                };
+                let delta_entropy = -context_log_prob.exp() * delta;
+                delta_entropy.exp() - 1.0 < threshold
+            });
+            prunable.push(level_prunable.collect());
+        }
+        self.remove_ngrams(&ngrams, &prunable)
+    }
+
+    /// Remove the n-grams above unigrams that were counted less often than the minimum count
+    ///
+    /// A model does not keep the counts of its n-grams, so the count of an n-gram is given by
+    /// the function from its words, for example by looking them up in the counts of the corpus
+    /// the model was trained on. Like when pruning by relative entropy, n-grams that are the
+    /// context or suffix of a longer n-gram that is kept are never removed and the backoff
+    /// weights are recomputed. `LanguageModelBuilder::min_count` leaves out the same n-grams
+    /// when training
+    pub fn prune_by_count(
+        &mut self,
+        count: impl Fn(&[&str]) -> u64,
+        min_count: u64,
+    ) -> Result<PruneReport, Error> {
+        let ngrams = self.to_ngrams();
+        let prunable: Vec<Vec<bool>> = ngrams
+            .iter()
+            .enumerate()
+            .map(|(level, entries)| {
+                entries
+                    .iter()
+                    .map(|(words, _, _)| {
+                        let symbols: Vec<&str> = words
+                            .iter()
+                            .map(|&label| self.symt[label as usize].as_str())
+                            .collect();
+                        level > 0 && count(&symbols) < min_count
+                    })
+                    .collect()
+            })
+            .collect();
+        self.remove_ngrams(&ngrams, &prunable)
+    }
+
+    /// Remove the prunable n-grams unless a longer n-gram that is kept needs them, then rebuild
+    /// the model and recompute the backoff weights of the contexts that lost n-grams
+    fn remove_ngrams(
+        &mut self,
+        ngrams: &[Vec<NgramEntry>],
+        prunable: &[Vec<bool>],
+    ) -> Result<PruneReport, Error> {
+        let ngrams_before = self.no_ngrams();
+        let bytes_before = self.memory_size();
+        let order = ngrams.len();
+        let mut kept: Vec<Vec<bool>> = ngrams
+            .iter()
+            .map(|entries| vec![true; entries.len()])
+            .collect();
+        for level in (1..order).rev() {
+            // The n-grams linked to by the kept n-grams of the next order are needed
+            let mut needed: HashSet<&[Label]> = HashSet::new();
+            if let Some(longer_ngrams) = ngrams.get(level + 1) {
+                for ((words, _, _), _) in longer_ngrams
+                    .iter()
+                    .zip(&kept[level + 1])
+                    .filter(|(_, &is_kept)| is_kept)
+                {
+                    needed.insert(&words[..level + 1]);
+                    needed.insert(&words[1..]);
+                }
+            }
+            for (idx, (words, _, _)) in ngrams[level].iter().enumerate() {
+                if prunable[level][idx] && !needed.contains(&words[..]) {
+                    kept[level][idx] = false;
+                }
+            }
+        }
+
+        let mut pruned_ngrams: Vec<Vec<NgramEntry>> = ngrams
+            .iter()
+            .zip(&kept)
+            .map(|(entries, kept)| {
+                entries
+                    .iter()
+                    .zip(kept)
+                    .filter(|(_, &is_kept)| is_kept)
+                    .map(|(entry, _)| entry.clone())
+                    .collect()
+            })
+            .collect();
+        if self.smoothing != Smoothing::StupidBackoff {
+            let changed_contexts: HashSet<&[Label]> = ngrams
+                .iter()
+                .zip(&kept)
+                .flat_map(|(entries, kept)| entries.iter().zip(kept))
+                .filter(|(_, &is_kept)| !is_kept)
+                .map(|((words, _, _), _)| &words[..words.len() - 1])
+                .collect();
+            recompute_backoffs(&mut pruned_ngrams, |context| {
+                changed_contexts.contains(context)
+            });
+        }
+        *self = Self::from_ngrams(self.symt.clone(), pruned_ngrams, self.smoothing)?;
+
+        Ok(PruneReport {
+            ngrams_before,
+            ngrams_after: self.no_ngrams(),
+            bytes_before,
+            bytes_after: self.memory_size(),
+        })
+    }
+
+    /// Get the number of bytes used by the n-grams and the symbol table
+    pub(crate) fn memory_size(&self) -> usize {
+        let symt_size: usize = self.symt.iter().map(String::len).sum();
+        symt_size + self.levels.iter().map(Level::memory_size).sum::<usize>()
+    }
+}
+
+/// Sum up the probabilities of the n-grams of each context and the probabilities of their
+/// suffixes
+fn context_sums<'a>(
+    entries: &'a [NgramEntry],
+    log_prob: &impl Fn(&[Label]) -> f64,
+) -> HashMap<&'a [Label], (f64, f64)> {
+    let mut sums: HashMap<&[Label], (f64, f64)> = HashMap::new();
+    for (words, _, _) in entries {
+        let sum = sums.entry(&words[..words.len() - 1]).or_insert((0.0, 0.0));
+        sum.0 += log_prob(words).exp();
+        sum.1 += log_prob(&words[1..]).exp();
+    }
+    sums
+}
+
+/// Recompute the backoff weights of the changed contexts, so the probability left by the
+/// n-grams of a context is distributed over the other words
+///
+/// The suffix of every n-gram has to be stored, so the probability of the other words can be
+/// found without backing off
+pub(crate) fn recompute_backoffs(
+    ngrams: &mut [Vec<NgramEntry>],
+    is_changed: impl Fn(&[Label]) -> bool,
+) {
+    let mut backoffs = Vec::new();
+    {
+        let log_probs: Vec<HashMap<&[Label], f64>> = ngrams
+            .iter()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|(words, log_prob, _)| (&words[..], *log_prob as f64))
+                    .collect()
+            })
+            .collect();
+        let log_prob = |words: &[Label]| log_probs[words.len() - 1][words];
+        for level in 1..ngrams.len() {
+            let sums = context_sums(&ngrams[level], &log_prob);
+            for (idx, (words, _, _)) in ngrams[level - 1].iter().enumerate() {
+                if !is_changed(words) {
+                    continue;
+                }
+                let (seen, seen_shorter) = sums.get(&words[..]).copied().unwrap_or((0.0, 0.0));
+                let (left, left_shorter) = (1.0 - seen, 1.0 - seen_shorter);
+                if left > 0.0 && left_shorter > 0.0 {
+                    backoffs.push((level - 1, idx, (left / left_shorter).ln() as LogProb));
+                }
+            }
+        }
+    }
+    for (level, idx, backoff) in backoffs {
+        ngrams[level][idx].2 = Some(backoff);
+    }
+}
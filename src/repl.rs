@@ -0,0 +1,191 @@
+use std::io::{self, BufRead, Write};
+
+use language_model::*;
+
+const HELP: &str = "Enter words to read them, they are separated by whitespace. Commands:
+  :reset     start again without any words
+  :undo      forget the last word
+  :k <no>    change the number of predictions
+  :help      print this message
+  :quit      exit";
+
+/// Reads words line by line and prints the state of the model and its predictions after each
+/// word
+pub struct Repl<'a> {
+    language_model: &'a LanguageModel,
+    // The number of predictions
+    k: usize,
+    // The state before reading any word
+    start_state: LMState,
+    // The words read and the state after reading each of them
+    words: Vec<String>,
+    states: Vec<LMState>,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(language_model: &'a LanguageModel, k: usize, sentence: bool) -> Self {
+        let start_state = if sentence {
+            LMState::sentence_start()
+        } else {
+            LMState::default()
+        };
+        Self {
+            language_model,
+            k,
+            start_state,
+            words: Vec::new(),
+            states: Vec::new(),
+        }
+    }
+
+    /// Process the lines of the input until it ends or the user quits
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}", HELP)?;
+        self.print_state(&mut output)?;
+        let mut lines = input.lines();
+        loop {
+            write!(output, "> ")?;
+            output.flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return writeln!(output),
+            };
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                None => continue,
+                Some(":quit") => return Ok(()),
+                Some(":help") => {
+                    writeln!(output, "{}", HELP)?;
+                    continue;
+                }
+                Some(":reset") => {
+                    self.words.clear();
+                    self.states.clear();
+                }
+                Some(":undo") => {
+                    if self.words.pop().is_none() {
+                        writeln!(output, "There is no word to undo")?;
+                        continue;
+                    }
+                    self.states.pop();
+                }
+                Some(":k") => match tokens.next().map(str::parse) {
+                    Some(Ok(k)) => self.k = k,
+                    _ => {
+                        writeln!(output, "Usage: :k <number of predictions>")?;
+                        continue;
+                    }
+                },
+                Some(command) if command.starts_with(':') => {
+                    writeln!(output, "Unknown command {}, enter :help for help", command)?;
+                    continue;
+                }
+                Some(_) => {
+                    for word in line.split_whitespace() {
+                        if self.language_model.label(word).is_none() {
+                            writeln!(output, "The word {} is not in the vocabulary", word)?;
+                        }
+                        let lm_state = self.language_model.get_next_state(self.state(), word);
+                        self.words.push(word.to_string());
+                        self.states.push(lm_state);
+                    }
+                }
+            }
+            self.print_state(&mut output)?;
+        }
+    }
+
+    /// Get the state after reading all words
+    fn state(&self) -> LMState {
+        self.states.last().copied().unwrap_or(self.start_state)
+    }
+
+    /// Print the words read, the words of the context the model uses, the number of orders it
+    /// backed off and the predictions
+    fn print_state<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let lm_state = self.state();
+        // The sentence start symbol is part of the context if the model has it
+        let mut context: Vec<&str> = Vec::with_capacity(self.words.len() + 1);
+        if self.start_state == LMState::sentence_start()
+            && self.language_model.label(SENTENCE_START).is_some()
+        {
+            context.push(SENTENCE_START);
+        }
+        context.extend(self.words.iter().map(String::as_str));
+        let context_len = lm_state.context_len();
+        // The model backs off if it uses fewer words than the words read and its order allow
+        let backed_off = context
+            .len()
+            .min(self.language_model.order() - 1)
+            .saturating_sub(context_len);
+
+        writeln!(output, "Words: {}", self.words.join(" "))?;
+        writeln!(
+            output,
+            "Context of length {}, backed off {} orders: {}",
+            context_len,
+            backed_off,
+            context[context.len().saturating_sub(context_len)..].join(" ")
+        )?;
+        for (symbol, log_prob) in self.language_model.predict(lm_state, self.k) {
+            writeln!(output, "  {:<20} {:.6}", symbol, log_prob)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    /// Drive the interactive mode with commands and words and check what it prints
+    fn test_repl() {
+        let language_model = LanguageModel::train("the cat sat\nthe dog sat\n").unwrap();
+        let input = "the\n:undo\n:undo\n:k 1\nzebra the cat\n:k\n:reset\nsat the\n:reset\n:bogus\n:quit\nthe\n";
+        let mut output = Vec::new();
+        Repl::new(&language_model, 2, false)
+            .run(Cursor::new(input), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        // The unknown word resets the context, the model backs off from a context it has not seen
+        // and the lines after quitting are not read
+        let expected = "\
+Words: 
+Context of length 0, backed off 0 orders: 
+  the                  -1.098612
+  sat                  -1.098612
+> Words: the
+Context of length 1, backed off 0 orders: the
+  cat                  -0.693147
+  dog                  -0.693147
+> Words: 
+Context of length 0, backed off 0 orders: 
+  the                  -1.098612
+  sat                  -1.098612
+> There is no word to undo
+> Words: 
+Context of length 0, backed off 0 orders: 
+  the                  -1.098612
+> The word zebra is not in the vocabulary
+Words: zebra the cat
+Context of length 2, backed off 0 orders: the cat
+  sat                  0.000000
+> Usage: :k <number of predictions>
+> Words: 
+Context of length 0, backed off 0 orders: 
+  the                  -1.098612
+> Words: sat the
+Context of length 1, backed off 1 orders: the
+  cat                  -0.693147
+> Words: 
+Context of length 0, backed off 0 orders: 
+  the                  -1.098612
+> Unknown command :bogus, enter :help for help
+> ";
+        assert!(output.strip_prefix(HELP).unwrap().trim_start_matches('\n') == expected);
+    }
+}
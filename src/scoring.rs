@@ -0,0 +1,169 @@
+use super::*;
+
+/// The score of a sequence of words
+#[derive(Clone, PartialEq, Debug)]
+pub struct SequenceScore {
+    /// Sum of the log probabilities of all scored words
+    pub log_prob: LogProb,
+    /// Score of each word of the sequence, followed by the score of the end of the sentence
+    /// if it was scored
+    pub tokens: Vec<TokenScore>,
+}
+
+impl SequenceScore {
+    /// Number of words not in the symbol table
+    pub fn no_oovs(&self) -> usize {
+        self.tokens.iter().filter(|token| token.oov).count()
+    }
+
+    /// Number of words that contribute to the log probability
+    pub fn no_scored(&self) -> usize {
+        self.tokens.iter().filter(|token| token.order > 0).count()
+    }
+}
+
+/// The scores of the sequences of a corpus summed up
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct CorpusScore {
+    /// Sum of the log probabilities of all sequences
+    pub log_prob: f64,
+    /// Number of sequences added
+    pub no_sequences: usize,
+    /// Number of words that contribute to the log probability
+    pub no_scored: usize,
+    /// Number of words not in the symbol table
+    pub no_oovs: usize,
+}
+
+impl CorpusScore {
+    /// Add the score of a sequence
+    pub fn add(&mut self, score: &SequenceScore) {
+        self.log_prob += score.log_prob as f64;
+        self.no_sequences += 1;
+        self.no_scored += score.no_scored();
+        self.no_oovs += score.no_oovs();
+    }
+
+    /// The perplexity over all scored words, which is infinite if no word was scored
+    pub fn perplexity(&self) -> f64 {
+        if self.no_scored == 0 {
+            return f64::INFINITY;
+        }
+        (-self.log_prob / self.no_scored as f64).exp()
+    }
+}
+
+/// The score of a single word given the words before it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TokenScore {
+    /// Log probability of the word including the backoff penalties. It is zero if the word is
+    /// not in the symbol table and the model has no unknown symbol
+    pub log_prob: LogProb,
+    /// Order of the n-gram the probability was taken from or zero if the word was not scored
+    pub order: usize,
+    /// Number of times the model had to backoff to a shorter context
+    pub backoffs: usize,
+    /// True if the word is not in the symbol table. It is scored as `<unk>` if the model has it
+    pub oov: bool,
+}
+
+impl LanguageModel {
+    /// Score a sequence of words starting from the initial state
+    ///
+    /// Words not in the symbol table are scored as `<unk>` if the model has it. Otherwise they
+    /// do not contribute to the log probability of the sequence and reset the context
+    pub fn score_sequence(&self, symbols: &[&str]) -> SequenceScore {
+        self.score_from(LMState::default(), symbols).0
+    }
+
+    /// Score a sentence starting from the sentence start state
+    ///
+    /// The probability of `</s>` after the last word is added if the model has it, so the
+    /// score includes the probability of the sentence ending there. Unknown words are handled
+    /// like by `score_sequence`
+    pub fn score_sentence(&self, symbols: &[&str]) -> SequenceScore {
+        let (mut score, lm_state) = self.score_from(LMState::sentence_start(), symbols);
+        if let Some(label) = self.label(SENTENCE_END) {
+            let token = self.score_label(lm_state, label);
+            score.log_prob += token.log_prob;
+            score.tokens.push(token);
+        }
+        score
+    }
+
+    /// Compute the perplexity of the model on a text corpus
+    ///
+    /// Each line of the corpus is a sentence, the words are separated by whitespace. The
+    /// sentences are scored with `score_sentence`, so the end of each sentence counts as a
+    /// word if the model has `</s>`. Unknown words are skipped unless the model has `<unk>`.
+    /// If no word was scored, the perplexity is infinite
+    pub fn perplexity(&self, corpus: &str) -> f32 {
+        let mut corpus_score = CorpusScore::default();
+        for line in corpus.lines() {
+            let symbols: Vec<&str> = line.split_whitespace().collect();
+            corpus_score.add(&self.score_sentence(&symbols));
+        }
+        corpus_score.perplexity() as f32
+    }
+
+    /// Score the words read one after the other starting in the provided state
+    /// Returns the score and the state after reading the words
+    fn score_from(&self, lm_state: LMState, symbols: &[&str]) -> (SequenceScore, LMState) {
+        let mut lm_state = self.enter_sentence(lm_state);
+        let mut log_prob = 0.0;
+        let mut tokens = Vec::with_capacity(symbols.len() + 1);
+
+        for symbol in symbols {
+            let oov = !self.symt.contains(*symbol);
+            let token = match self.label_or_unknown(symbol) {
+                Some(label) => TokenScore {
+                    oov,
+                    ..self.score_label(lm_state, label)
+                },
+                None => TokenScore {
+                    log_prob: 0.0,
+                    order: 0,
+                    backoffs: 0,
+                    oov,
+                },
+            };
+            log_prob += token.log_prob;
+            tokens.push(token);
+            lm_state = self.get_next_state(lm_state, symbol);
+        }
+
+        (SequenceScore { log_prob, tokens }, lm_state)
+    }
+
+    /// Get the score of reading the label in the provided state
+    fn score_label(&self, lm_state: LMState, label: Label) -> TokenScore {
+        let mut lm_state = lm_state;
+        let mut backoff_penalty = 0.0;
+        let mut backoffs = 0;
+
+        // Look for the n-gram made of the context and the label, backoff if there is none
+        while lm_state.context_len > 0 {
+            let level = &self.levels[lm_state.context_len].ngrams;
+            let ngrams =
+                &level[lm_state.ngrams_offset..lm_state.ngrams_offset + lm_state.ngrams_no];
+            if let Ok(idx) = ngrams.binary_search_by_key(&label, |&(a, _)| a) {
+                return TokenScore {
+                    log_prob: ngrams[idx].1 + backoff_penalty,
+                    order: lm_state.context_len + 1,
+                    backoffs,
+                    oov: false,
+                };
+            }
+            backoff_penalty += self.backoff_weight(lm_state);
+            lm_state = self.backoff(lm_state);
+            backoffs += 1;
+        }
+        // Every known word has a unigram
+        TokenScore {
+            log_prob: self.levels[0].ngrams[label as usize].1 + backoff_penalty,
+            order: 1,
+            backoffs,
+            oov: false,
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use super::*;
+
+/// The number of buckets of the histograms of the log probabilities
+const HISTOGRAM_BUCKETS: usize = 20;
+
+/// The size and shape of a language model
+#[derive(Clone, PartialEq, Debug)]
+pub struct ModelStats {
+    /// The number of words in the symbol table
+    pub vocabulary_size: usize,
+    /// The smoothing used to back off
+    pub smoothing: Smoothing,
+    /// The number of bytes used by the symbols of the symbol table
+    pub symt_bytes: usize,
+    /// The statistics of each order, starting with the unigrams
+    pub orders: Vec<OrderStats>,
+}
+
+/// The statistics of the n-grams of one order
+#[derive(Clone, PartialEq, Debug)]
+pub struct OrderStats {
+    /// The number of words of the n-grams
+    pub order: usize,
+    /// The number of n-grams
+    pub no_ngrams: usize,
+    /// The number of n-grams that are the context of at least one n-gram of the next order
+    pub no_contexts: usize,
+    /// The average number of n-grams of the next order following a context, 0 for the highest
+    /// order
+    pub average_fan_out: f64,
+    /// The highest number of n-grams of the next order following a context
+    pub max_fan_out: usize,
+    /// The number of bytes used by the labels and log probabilities of the n-grams
+    pub ngrams_bytes: usize,
+    /// The number of bytes used by the offsets of the children
+    pub offsets_bytes: usize,
+    /// The number of bytes used by the indices of the suffixes
+    pub suffixes_bytes: usize,
+    /// The number of bytes used by the backoff weights
+    pub backoffs_bytes: usize,
+    /// The distribution of the log probabilities of the n-grams
+    pub log_probs: LogProbStats,
+}
+
+/// The distribution of log probabilities
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct LogProbStats {
+    /// The lowest log probability, 0 if there are none
+    pub min: LogProb,
+    /// The highest log probability, 0 if there are none
+    pub max: LogProb,
+    /// The mean of the log probabilities, 0 if there are none
+    pub mean: f64,
+    /// The number of log probabilities in each bucket of width one, the bucket at index i holds
+    /// the ones above -(i + 1) up to -i. The last bucket also holds all lower ones, it is empty
+    /// if there are no log probabilities
+    pub histogram: Vec<usize>,
+}
+
+impl ModelStats {
+    /// Get the number of n-grams of all orders
+    pub fn total_ngrams(&self) -> usize {
+        self.orders.iter().map(|order| order.no_ngrams).sum()
+    }
+
+    /// Get the number of bytes used by the symbol table and the n-grams of all orders
+    pub fn total_bytes(&self) -> usize {
+        self.symt_bytes + self.orders.iter().map(OrderStats::bytes).sum::<usize>()
+    }
+}
+
+impl OrderStats {
+    /// Get the number of bytes used by the n-grams, their links and backoff weights
+    pub fn bytes(&self) -> usize {
+        self.ngrams_bytes + self.offsets_bytes + self.suffixes_bytes + self.backoffs_bytes
+    }
+}
+
+impl LogProbStats {
+    fn new(log_probs: impl Iterator<Item = LogProb>) -> Self {
+        let mut stats = LogProbStats {
+            min: LogProb::INFINITY,
+            max: LogProb::NEG_INFINITY,
+            mean: 0.0,
+            histogram: vec![0; HISTOGRAM_BUCKETS],
+        };
+        let (mut sum, mut no) = (0.0, 0);
+        for log_prob in log_probs {
+            stats.min = stats.min.min(log_prob);
+            stats.max = stats.max.max(log_prob);
+            sum += log_prob as f64;
+            no += 1;
+            let bucket = ((-log_prob).max(0.0) as usize).min(HISTOGRAM_BUCKETS - 1);
+            stats.histogram[bucket] += 1;
+        }
+        if no == 0 {
+            return LogProbStats::default();
+        }
+        stats.mean = sum / no as f64;
+        stats
+    }
+}
+
+impl LanguageModel {
+    /// Get the size of the vocabulary, the number of n-grams of each order, how many words
+    /// follow each context, the memory used by each part of the model and the distribution
+    /// of the log probabilities
+    pub fn stats(&self) -> ModelStats {
+        let orders = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(idx, level)| {
+                let fan_outs = (0..level.ngrams.len())
+                    .map(|idx| level.children(idx).len())
+                    .filter(|&no| no > 0);
+                let (no_contexts, no_children, max_fan_out) = fan_outs
+                    .fold((0, 0, 0), |(contexts, children, max), no| {
+                        (contexts + 1, children + no, max.max(no))
+                    });
+                OrderStats {
+                    order: idx + 1,
+                    no_ngrams: level.ngrams.len(),
+                    no_contexts,
+                    average_fan_out: if no_contexts == 0 {
+                        0.0
+                    } else {
+                        no_children as f64 / no_contexts as f64
+                    },
+                    max_fan_out,
+                    ngrams_bytes: level.ngrams.len() * std::mem::size_of::<Ngram>(),
+                    offsets_bytes: level.offsets.memory_size(),
+                    suffixes_bytes: level.suffixes.memory_size(),
+                    backoffs_bytes: level.backoffs.len() * std::mem::size_of::<LogProb>(),
+                    log_probs: LogProbStats::new(level.ngrams.iter().map(|ngram| ngram.1)),
+                }
+            })
+            .collect();
+        ModelStats {
+            vocabulary_size: self.symt.len(),
+            smoothing: self.smoothing,
+            symt_bytes: self.symt.iter().map(String::len).sum(),
+            orders,
+        }
+    }
+}
+
+impl fmt::Display for ModelStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Order: {}", self.orders.len())?;
+        writeln!(f, "Smoothing: {:?}", self.smoothing)?;
+        writeln!(f, "Vocabulary size: {}", self.vocabulary_size)?;
+        writeln!(f, "N-grams: {}", self.total_ngrams())?;
+        writeln!(f, "Memory: {} bytes", self.total_bytes())?;
+        writeln!(f, "  symbol table: {} bytes", self.symt_bytes)?;
+        for order in &self.orders {
+            writeln!(f)?;
+            writeln!(f, "{}-grams: {}", order.order, order.no_ngrams)?;
+            if order.no_contexts > 0 {
+                writeln!(
+                    f,
+                    "  fan-out: {:.2} on average, {} at most over {} contexts",
+                    order.average_fan_out, order.max_fan_out, order.no_contexts
+                )?;
+            }
+            writeln!(
+                f,
+                "  memory: {} bytes (n-grams {}, offsets {}, suffixes {}, backoffs {})",
+                order.bytes(),
+                order.ngrams_bytes,
+                order.offsets_bytes,
+                order.suffixes_bytes,
+                order.backoffs_bytes
+            )?;
+            let log_probs = &order.log_probs;
+            writeln!(
+                f,
+                "  log probs: min {:.4}, mean {:.4}, max {:.4}",
+                log_probs.min, log_probs.mean, log_probs.max
+            )?;
+            for (bucket, no) in log_probs.histogram.iter().enumerate() {
+                if *no == 0 {
+                    continue;
+                }
+                // The last bucket also holds the lower log probabilities
+                if bucket == log_probs.histogram.len() - 1 {
+                    writeln!(f, "    ≤ {}: {}", -(bucket as i64), no)?;
+                } else {
+                    writeln!(
+                        f,
+                        "    ({}, {}]: {}",
+                        -(bucket as i64 + 1),
+                        -(bucket as i64),
+                        no
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,11 @@
+#![allow(clippy::approx_constant, clippy::excessive_precision)]
+
 use super::*;
 
+#[path = "../tests/common/mod.rs"]
+mod common;
+use common::TempFile;
+
 // Check if the two Vecs are equal
 fn cmp(a: Vec<(&str, f32)>, b: Vec<(&str, f32)>) -> bool {
     println!("len a: {}, len b: {}", a.len(), b.len());
@@ -18,7 +24,7 @@ fn cmp(a: Vec<(&str, f32)>, b: Vec<(&str, f32)>) -> bool {
         return false;
     }
     for idx in 0..a.len() {
-        if b[idx].0 != b[idx].0 {
+        if a[idx].0 != b[idx].0 {
             return false;
         }
         if (a[idx].1 - b[idx].1).abs() > 0.00001 {
@@ -28,15 +34,48 @@ fn cmp(a: Vec<(&str, f32)>, b: Vec<(&str, f32)>) -> bool {
     true
 }
 
+// The corpus the trained models of the tests are built from
+const CORPUS: &str = "the cat sat on the mat\nthe cat sat on the hat\nthe dog sat on the log\n\
+                      a cat ate the fish\nthe dog ate a bone\n";
+
+// Build a trigram model from the corpus
+fn build_from_corpus(smoothing: Smoothing) -> LanguageModel {
+    LanguageModelBuilder::new()
+        .add_text(CORPUS)
+        .smoothing(smoothing)
+        .build()
+        .unwrap()
+}
+
+// Read the model of the text files in the test folder
+fn read_test_model() -> LanguageModel {
+    LanguageModel::read_from_text(
+        "ngrams_test/symt.txt",
+        "ngrams_test/1gms.txt",
+        "ngrams_test/2gms.txt",
+        "ngrams_test/3gms.txt",
+    )
+    .unwrap()
+}
+
+// Convert the text files of the test folder to a binary model in a temporary file, so the tests
+// running in parallel do not write the same file
+fn convert_test_model(name: &str) -> TempFile {
+    let file = TempFile::new(name);
+    let config = ConvertConfig::from_text_folder("ngrams_test", file.path());
+    LanguageModel::convert(config).unwrap();
+    file
+}
+
 #[test]
 /// Test case D1
 /// Convert the text files to the language model and write it to a
 /// compressed binary file
 fn test_convert_and_load_model() {
     // Convert the text files to the language model and write it to a compressed file
-    convert_text_to_cmprssd_bin(true).unwrap();
+    let file_language_model = convert_test_model("convert_and_load.bin");
     println!("Loading language model from file...");
-    let fname_language_model = "ngrams_test/language_model.bin";
+    let fname_language_model = file_language_model.path();
 
     // Read it from the compressed binary file
     let language_model = LanguageModel::read(fname_language_model).unwrap();
@@ -46,23 +85,27 @@ fn test_convert_and_load_model() {
     let mut correct_symt = IndexSet::new();
     correct_symt.insert("a".to_string());
     correct_symt.insert("b".to_string());
-    let correct_unigrams = vec![(-0.6931472, 0, 1), (-0.6931472, 1, 2)];
-    let correct_bigrams = vec![
-        (1, -0.40546507, 0, 2),
-        (0, -0.40546507, 2, 1),
-        (1, -1.0986123, 3, 1),
-    ];
-    let correct_trigrams = vec![
-        (0, -0.6931472, 1),
-        (1, -0.6931472, 2),
-        (1, -0.6931472, 0),
-        (0, 0.0, 1),
-    ];
+    let correct_unigrams = Level {
+        ngrams: vec![(0, -0.6931472), (1, -0.6931472)],
+        offsets: Indices::Narrow(vec![0, 1, 3]),
+        ..Default::default()
+    };
+    let correct_bigrams = Level {
+        ngrams: vec![(1, -0.40546507), (0, -0.40546507), (1, -1.0986123)],
+        offsets: Indices::Narrow(vec![0, 2, 3, 4]),
+        ..Default::default()
+    };
+    let correct_trigrams = Level {
+        ngrams: vec![(0, -0.6931472), (1, -0.6931472), (1, -0.6931472), (0, 0.0)],
+        suffixes: Indices::Narrow(vec![1, 2, 0, 1]),
+        ..Default::default()
+    };
     let correct_lm = LanguageModel {
         symt: correct_symt,
-        unigrams: correct_unigrams,
-        bigrams: correct_bigrams,
-        trigrams: correct_trigrams,
+        levels: vec![correct_unigrams, correct_bigrams, correct_trigrams],
+        smoothing: Smoothing::StupidBackoff,
+        sorted_labels: SortedLabels::default(),
+        ranked_labels: SortedLabels::default(),
     };
     assert!(language_model == correct_lm);
 }
@@ -71,9 +114,9 @@ fn test_convert_and_load_model() {
 /// Test case D2
 /// Test transitioning to the next state (no backoff required)
 fn test_valid_transitions() {
-    convert_text_to_cmprssd_bin(true).unwrap();
+    let file_language_model = convert_test_model("valid_transitions.bin");
     println!("Loading language model from file...");
-    let fname_language_model = "ngrams_test/language_model.bin";
+    let fname_language_model = file_language_model.path();
     let language_model = LanguageModel::read(fname_language_model).unwrap();
     println!("Done loading");
     println!();
@@ -184,9 +227,9 @@ fn test_valid_transitions() {
 /// Test case D3
 /// Test transitioning to the next state (backoff required)
 fn test_invalid_transitions() {
-    convert_text_to_cmprssd_bin(true).unwrap();
+    let file_language_model = convert_test_model("invalid_transitions.bin");
     println!("Loading language model from file...");
-    let fname_language_model = "ngrams_test/language_model.bin";
+    let fname_language_model = file_language_model.path();
     let language_model = LanguageModel::read(fname_language_model).unwrap();
     println!("Done loading");
     println!();
@@ -229,9 +272,9 @@ fn test_invalid_transitions() {
 /// Test case D4
 /// Backoff to the state associated with the suffix
 fn test_backoff() {
-    convert_text_to_cmprssd_bin(true).unwrap();
+    let file_language_model = convert_test_model("backoff.bin");
     println!("Loading language model from file...");
-    let fname_language_model = "ngrams_test/language_model.bin";
+    let fname_language_model = file_language_model.path();
     let language_model = LanguageModel::read(fname_language_model).unwrap();
     println!("Done loading");
     println!();
@@ -304,9 +347,9 @@ fn test_backoff() {
 /// Test case D5
 /// Test transitions, backoffs and predictions
 fn test_transitions_and_backoffs() {
-    convert_text_to_cmprssd_bin(true).unwrap();
+    let file_language_model = convert_test_model("transitions_and_backoffs.bin");
     println!("Loading language model from file...");
-    let fname_language_model = "ngrams_test/language_model.bin";
+    let fname_language_model = file_language_model.path();
     let language_model = LanguageModel::read(fname_language_model).unwrap();
     println!("Done loading");
     println!();
@@ -320,9 +363,11 @@ fn test_transitions_and_backoffs() {
     lm_state = LMState::default();
     correct_state = LMState {
         last_processed_label: 0,
+        context_idx: 0,
         ngrams_offset: 0,
         ngrams_no: usize::MAX,
-        context_len: LMContext::Zero,
+        context_len: 0,
+        at_sentence_start: false,
     };
     assert!(lm_state == correct_state);
     predictions = language_model.predict(lm_state, 10);
@@ -335,9 +380,11 @@ fn test_transitions_and_backoffs() {
     lm_state = language_model.get_next_state(lm_state, "a");
     correct_state = LMState {
         last_processed_label: 0,
+        context_idx: 0,
         ngrams_offset: 0,
         ngrams_no: 1,
-        context_len: LMContext::One,
+        context_len: 1,
+        at_sentence_start: false,
     };
     assert!(lm_state == correct_state);
     predictions = language_model.predict(lm_state, 10);
@@ -350,9 +397,11 @@ fn test_transitions_and_backoffs() {
     lm_state = language_model.get_next_state(lm_state, "b");
     correct_state = LMState {
         last_processed_label: 1,
+        context_idx: 0,
         ngrams_offset: 0,
         ngrams_no: 2,
-        context_len: LMContext::Two,
+        context_len: 2,
+        at_sentence_start: false,
     };
     assert!(lm_state == correct_state);
     predictions = language_model.predict(lm_state, 10);
@@ -365,9 +414,11 @@ fn test_transitions_and_backoffs() {
     lm_state = language_model.get_next_state(lm_state, "b");
     correct_state = LMState {
         last_processed_label: 1,
+        context_idx: 2,
         ngrams_offset: 3,
         ngrams_no: 1,
-        context_len: LMContext::Two,
+        context_len: 2,
+        at_sentence_start: false,
     };
     assert!(lm_state == correct_state);
     predictions = language_model.predict(lm_state, 10);
@@ -380,9 +431,11 @@ fn test_transitions_and_backoffs() {
     lm_state = language_model.backoff(lm_state);
     correct_state = LMState {
         last_processed_label: 1,
+        context_idx: 1,
         ngrams_offset: 1,
         ngrams_no: 2,
-        context_len: LMContext::One,
+        context_len: 1,
+        at_sentence_start: false,
     };
     assert!(lm_state == correct_state);
     predictions = language_model.predict(lm_state, 10);
@@ -394,46 +447,53 @@ fn test_transitions_and_backoffs() {
 
 fn get_test_state_no(state_no: usize) -> LMState {
     let last_processed_label;
+    let context_idx;
     let ngrams_offset;
     let ngrams_no;
     let context_len;
 
     match state_no {
         0 => {
+            context_idx = 0;
             last_processed_label = 0;
             ngrams_offset = 0;
             ngrams_no = usize::MAX;
-            context_len = LMContext::Zero
+            context_len = 0
         }
         1 => {
+            context_idx = 0;
             last_processed_label = 0;
             ngrams_offset = 0;
             ngrams_no = 1;
-            context_len = LMContext::One
+            context_len = 1
         }
         2 => {
+            context_idx = 1;
             last_processed_label = 1;
             ngrams_offset = 1;
             ngrams_no = 2;
-            context_len = LMContext::One
+            context_len = 1
         }
         3 => {
+            context_idx = 0;
             last_processed_label = 1;
             ngrams_offset = 0;
             ngrams_no = 2;
-            context_len = LMContext::Two
+            context_len = 2
         }
         4 => {
+            context_idx = 1;
             last_processed_label = 0;
             ngrams_offset = 2;
             ngrams_no = 1;
-            context_len = LMContext::Two
+            context_len = 2
         }
         5 => {
+            context_idx = 2;
             last_processed_label = 1;
             ngrams_offset = 3;
             ngrams_no = 1;
-            context_len = LMContext::Two
+            context_len = 2
         }
         _ => {
             println!("Asked for the invalid state no: {}", state_no);
@@ -443,8 +503,1369 @@ fn get_test_state_no(state_no: usize) -> LMState {
 
     LMState {
         last_processed_label,
+        context_idx,
         ngrams_offset,
         ngrams_no,
         context_len,
+        at_sentence_start: false,
+    }
+}
+
+#[test]
+/// Test case D6
+/// Train a language model on a small corpus and check the counts and links
+fn test_train() {
+    let language_model = LanguageModel::train("a b a b\nb a b b\n").unwrap();
+
+    let mut correct_symt = IndexSet::new();
+    correct_symt.insert("a".to_string());
+    correct_symt.insert("b".to_string());
+    // a: 3/8, b: 5/8
+    let correct_unigrams = Level {
+        ngrams: vec![(0, -0.98082925), (1, -0.47000363)],
+        offsets: Indices::Narrow(vec![0, 1, 3]),
+        ..Default::default()
+    };
+    // a b: 3/3, b a: 2/3, b b: 1/3
+    let correct_bigrams = Level {
+        ngrams: vec![(1, 0.0), (0, -0.40546511), (1, -1.0986123)],
+        offsets: Indices::Narrow(vec![0, 2, 3, 3]),
+        ..Default::default()
+    };
+    // a b a: 1/2, a b b: 1/2, b a b: 2/2, b b ..: no trigram
+    let correct_trigrams = Level {
+        ngrams: vec![(0, -0.6931472), (1, -0.6931472), (1, 0.0)],
+        suffixes: Indices::Narrow(vec![1, 2, 0]),
+        ..Default::default()
+    };
+    let correct_lm = LanguageModel {
+        symt: correct_symt,
+        levels: vec![correct_unigrams, correct_bigrams, correct_trigrams],
+        smoothing: Smoothing::StupidBackoff,
+        sorted_labels: SortedLabels::default(),
+        ranked_labels: SortedLabels::default(),
+    };
+    assert!(language_model == correct_lm);
+
+    // The trained model can be used for predictions
+    let mut lm_state = LMState::default();
+    lm_state = language_model.get_next_state(lm_state, "b");
+    lm_state = language_model.get_next_state(lm_state, "a");
+    let predictions = language_model.predict(lm_state, 1);
+    assert!(cmp(predictions, vec![("b", 0.0)]));
+}
+
+#[test]
+/// Test case D7
+/// Read a language model from an ARPA file
+fn test_read_arpa() {
+    let language_model = LanguageModel::read_arpa("ngrams_test/model.arpa").unwrap();
+    let correct_lm = read_test_model();
+
+    // The ARPA file contains the same n-grams as the text files
+    assert!(language_model.symt == correct_lm.symt);
+    assert!(language_model.order() == 3);
+    for (level, correct_level) in language_model.levels.iter().zip(&correct_lm.levels) {
+        for (a, b) in level.ngrams.iter().zip(&correct_level.ngrams) {
+            assert!((a.1 - b.1).abs() < 0.00001 && a.0 == b.0);
+        }
+        assert!(level.ngrams.len() == correct_level.ngrams.len());
+        assert!(level.offsets == correct_level.offsets);
+        assert!(level.suffixes == correct_level.suffixes);
+    }
+
+    // The backoff weights are kept and converted to natural logarithms
+    let correct_backoffs = [
+        vec![-0.4605170, -0.2302585],
+        vec![0.0, -0.1151293, -0.6907755],
+        vec![],
+    ];
+    for (level, correct_backoffs) in language_model.levels.iter().zip(&correct_backoffs) {
+        for (a, b) in level.backoffs.iter().zip(correct_backoffs) {
+            assert!((a - b).abs() < 0.00001);
+        }
+        assert!(level.backoffs.len() == correct_backoffs.len());
+    }
+}
+
+#[test]
+/// Test case D8
+/// Write a language model to an ARPA file and read it again
+fn test_write_arpa() {
+    let file_arpa = TempFile::new("write_arpa.arpa");
+    let fname_arpa = file_arpa.path();
+
+    // With backoff weights
+    let language_model = LanguageModel::read_arpa("ngrams_test/model.arpa").unwrap();
+    language_model.write_arpa(fname_arpa).unwrap();
+    let reread_model = LanguageModel::read_arpa(fname_arpa).unwrap();
+    assert!(reread_model == language_model);
+
+    // Without backoff weights
+    let language_model = read_test_model();
+    language_model.write_arpa(fname_arpa).unwrap();
+    let reread_model = LanguageModel::read_arpa(fname_arpa).unwrap();
+    for (level, correct_level) in reread_model.levels.iter().zip(&language_model.levels) {
+        assert!(level.backoffs.is_empty());
+        for (a, b) in level.ngrams.iter().zip(&correct_level.ngrams) {
+            assert!((a.1 - b.1).abs() < 0.00001 && a.0 == b.0);
+        }
+        assert!(level.ngrams.len() == correct_level.ngrams.len());
+        assert!(level.offsets == correct_level.offsets);
+        assert!(level.suffixes == correct_level.suffixes);
+    }
+}
+
+#[test]
+/// Test case D9
+/// Penalize backoffs with the stored backoff weights
+fn test_katz_backoff() {
+    let mut language_model = LanguageModel::read_arpa("ngrams_test/model.arpa").unwrap();
+    assert!(language_model.smoothing() == Smoothing::Katz);
+
+    // Transition to the context "b b" and backoff twice
+    let mut lm_state = LMState::default();
+    lm_state = language_model.get_next_state(lm_state, "b");
+    lm_state = language_model.get_next_state(lm_state, "b");
+    let predictions = language_model.predict(lm_state, 10);
+    // b: ln(1/3) + 0.3 * ln(0.1)
+    let correct_prediction = vec![("a", 0.0), ("b", -1.7893878)];
+    assert!(cmp(predictions, correct_prediction));
+
+    // The same model with stupid backoff
+    language_model.set_smoothing(Smoothing::StupidBackoff);
+    let predictions = language_model.predict(lm_state, 10);
+    let correct_prediction = vec![("a", 0.0), ("b", -2.01490306)];
+    assert!(cmp(predictions, correct_prediction));
+
+    // A model trained with Katz backoff predicts normalized probabilities in every context
+    let language_model = LanguageModelBuilder::new()
+        .add_text("a b c a b d\nb a c c b a\nc c a b b\n")
+        .smoothing(Smoothing::Katz)
+        .build()
+        .unwrap();
+    assert!(language_model.levels[1].backoffs.len() == language_model.levels[1].ngrams.len());
+    for context in [vec![], vec!["a"], vec!["c"], vec!["a", "b"], vec!["c", "c"]] {
+        let mut lm_state = LMState::default();
+        for symbol in context {
+            lm_state = language_model.get_next_state(lm_state, symbol);
+        }
+        let predictions = language_model.predict(lm_state, 4);
+        let total: f32 = predictions.iter().map(|(_, log_prob)| log_prob.exp()).sum();
+        assert!((total - 1.0).abs() < 0.00001);
+
+        // Fewer predictions are the most probable ones of the whole vocabulary, even if a word
+        // is only predicted after backing off
+        for max_no_predictions in 1..4 {
+            assert!(
+                language_model.predict(lm_state, max_no_predictions)
+                    == predictions[..max_no_predictions]
+            );
+        }
+    }
+}
+
+#[test]
+/// Test case D10
+/// Score sequences and compute the perplexity
+fn test_score_sequence() {
+    let language_model = read_test_model();
+
+    // Matches of every order and an unknown word
+    let score = language_model.score_sequence(&["a", "b", "b", "a", "c"]);
+    let correct_tokens = [
+        (-0.6931472, 1, 0, false),
+        (-0.40546507, 2, 0, false),
+        (-0.6931472, 3, 0, false),
+        (0.0, 3, 0, false),
+        (0.0, 0, 0, true),
+    ];
+    assert!(score.tokens.len() == correct_tokens.len());
+    for (token, &(log_prob, order, backoffs, oov)) in score.tokens.iter().zip(&correct_tokens) {
+        assert!((token.log_prob - log_prob).abs() < 0.00001);
+        assert!((token.order, token.backoffs, token.oov) == (order, backoffs, oov));
+    }
+    assert!((score.log_prob - -1.7917595).abs() < 0.00001);
+    assert!(score.no_oovs() == 1);
+
+    // Backoff from the context "a" to the unigram "a"
+    let score = language_model.score_sequence(&["a", "a"]);
+    assert!((score.tokens[1].log_prob - -1.6094379).abs() < 0.00001);
+    assert!((score.tokens[1].order, score.tokens[1].backoffs) == (1, 1));
+
+    // The perplexity is the inverse of the geometric mean of the probabilities
+    let perplexity = language_model.perplexity("a a\nc\n");
+    assert!((perplexity - 10.0_f32.sqrt()).abs() < 0.0001);
+    assert!(language_model.perplexity("c\n") == f32::INFINITY);
+
+    // The scores of the sequences are summed up
+    let mut corpus_score = CorpusScore::default();
+    corpus_score.add(&language_model.score_sequence(&["a", "a"]));
+    corpus_score.add(&language_model.score_sequence(&["c"]));
+    assert!(corpus_score.no_sequences == 2 && corpus_score.no_scored == 2);
+    assert!(corpus_score.no_oovs == 1);
+    assert!((corpus_score.perplexity() - (-corpus_score.log_prob / 2.0).exp()).abs() < 0.0001);
+}
+
+#[test]
+/// Test case D11
+/// Complete a partially entered word
+fn test_predict_with_prefix() {
+    let language_model =
+        LanguageModel::train("the man walked\nthe man walks\nthe wall fell\nthe man talked\n")
+            .unwrap();
+    let mut lm_state = LMState::default();
+    lm_state = language_model.get_next_state(lm_state, "the");
+    lm_state = language_model.get_next_state(lm_state, "man");
+
+    // The trigrams and unigrams starting with the prefix are found
+    let predictions = language_model.predict_with_prefix(lm_state, "wal", 3);
+    let correct_prediction = vec![
+        ("walked", -1.0986123),
+        ("walks", -1.0986123),
+        ("wall", -4.317488),
+    ];
+    assert!(cmp(predictions, correct_prediction));
+
+    // The number of predictions is limited
+    let predictions = language_model.predict_with_prefix(lm_state, "wal", 1);
+    assert!(cmp(predictions, vec![("walked", -1.0986123)]));
+
+    // A prefix no symbol starts with
+    assert!(language_model
+        .predict_with_prefix(lm_state, "x", 3)
+        .is_empty());
+
+    // An empty prefix does not restrict the predictions
+    assert!(cmp(
+        language_model.predict_with_prefix(lm_state, "", 10),
+        language_model.predict(lm_state, 10)
+    ));
+}
+
+#[test]
+/// Test case D12
+/// Report missing files and malformed lines as errors
+fn test_errors() {
+    let file_bad_unigrams = TempFile::new("errors_1gms.txt");
+    let fname_bad_unigrams = file_bad_unigrams.path();
+    std::fs::write(fname_bad_unigrams, "-0.6931472 0 1\n-0.6931472 x 2\n").unwrap();
+    let file_bad_arpa = TempFile::new("errors.arpa");
+    let fname_bad_arpa = file_bad_arpa.path();
+    std::fs::write(
+        fname_bad_arpa,
+        "\\data\\\nngram 1=1\nngram 2=1\n\n\\1-grams:\n-0.1 a\n\n\\2-grams:\n-0.1 a b\n\\end\\\n",
+    )
+    .unwrap();
+
+    // A missing file
+    let result = LanguageModel::read("ngrams_test/missing.bin");
+    assert!(matches!(result, Err(Error::Io(_))));
+    let result = LanguageModel::read_from_text(
+        "ngrams_test/missing.txt",
+        "ngrams_test/1gms.txt",
+        "ngrams_test/2gms.txt",
+        "ngrams_test/3gms.txt",
+    );
+    assert!(matches!(result, Err(Error::Io(_))));
+
+    // A line that can not be parsed
+    let result = LanguageModel::read_from_text(
+        "ngrams_test/symt.txt",
+        fname_bad_unigrams,
+        "ngrams_test/2gms.txt",
+        "ngrams_test/3gms.txt",
+    );
+    match result {
+        Err(Error::Parse { fname, line, .. }) => {
+            assert!(fname == fname_bad_unigrams);
+            assert!(line == 2);
+        }
+        _ => panic!("Expected a parse error"),
+    }
+
+    // A file that is not a compressed language model
+    let result = LanguageModel::read("ngrams_test/symt.txt");
+    assert!(matches!(result, Err(Error::Format { .. })));
+
+    // An ARPA file with a bigram of an unknown word
+    let result = LanguageModel::read_arpa(fname_bad_arpa);
+    assert!(matches!(result, Err(Error::Structure(_))));
+
+    // A truncated ARPA file has fewer n-grams than its header declares
+    let truncated_arpa = "\\data\\\nngram 1=5\n\n\\1-grams:\n-0.3 a\n-0.3 b\n";
+    let result = LanguageModel::read_arpa_from(truncated_arpa.as_bytes());
+    assert!(matches!(result, Err(Error::Structure(_))));
+    let complete_arpa = truncated_arpa.replace("1=5", "1=2");
+    assert!(LanguageModel::read_arpa_from(complete_arpa.as_bytes()).is_ok());
+    let result = LanguageModel::read_arpa_from("\\data\\\nngram 1=x\n".as_bytes());
+    assert!(matches!(result, Err(Error::Parse { line: 2, .. })));
+}
+
+#[test]
+/// Test case D13
+/// Read malformed text files in strict and lenient mode
+fn test_parse_modes() {
+    let file_bigrams = TempFile::new("parse_modes_2gms.txt");
+    let fname_bigrams = file_bigrams.path();
+    std::fs::write(
+        fname_bigrams,
+        "1 -0.40546507 0 2\n0 -0.40546507 2 1 junk\n1 -1.0986123 3 1\n",
+    )
+    .unwrap();
+    let file_trigrams = TempFile::new("parse_modes_3gms.txt");
+    let fname_trigrams = file_trigrams.path();
+    std::fs::write(
+        fname_trigrams,
+        "0 -0.6931472 1\n1 -0.6931472 2\n1 -0.6931472 0\n0 0 1\ngarbage\n",
+    )
+    .unwrap();
+
+    // The unexpected token is an error in strict mode
+    let result = LanguageModel::read_from_text(
+        "ngrams_test/symt.txt",
+        "ngrams_test/1gms.txt",
+        fname_bigrams,
+        "ngrams_test/3gms.txt",
+    );
+    match result {
+        Err(Error::Parse { line, column, .. }) => assert!((line, column) == (2, 19)),
+        _ => panic!("Expected a parse error"),
+    }
+
+    // The line that can not be parsed is an error in strict mode
+    let result = LanguageModel::read_from_text(
+        "ngrams_test/symt.txt",
+        "ngrams_test/1gms.txt",
+        "ngrams_test/2gms.txt",
+        fname_trigrams,
+    );
+    match result {
+        Err(Error::Parse { line, column, .. }) => assert!((line, column) == (5, 1)),
+        _ => panic!("Expected a parse error"),
+    }
+
+    // In lenient mode both problems are reported and the model is read anyway
+    let options = ReadOptions {
+        mode: ParseMode::Lenient,
+        ..Default::default()
+    };
+    let (language_model, report) = LanguageModel::read_from_text_with_options(
+        "ngrams_test/symt.txt",
+        "ngrams_test/1gms.txt",
+        fname_bigrams,
+        fname_trigrams,
+        options,
+    )
+    .unwrap();
+    let correct_lm = read_test_model();
+    assert!(language_model == correct_lm);
+    assert!(report.warnings.len() == 2);
+    assert!(matches!(
+        report.warnings[0],
+        Error::Parse {
+            line: 2,
+            column: 19,
+            ..
+        }
+    ));
+    assert!(matches!(
+        report.warnings[1],
+        Error::Parse {
+            line: 5,
+            column: 1,
+            ..
+        }
+    ));
+}
+
+#[test]
+/// Test case D14
+/// Validate the structure of language models
+fn test_validate() {
+    // Valid language models
+    let mut language_model = read_test_model();
+    assert!(language_model.validate().is_empty());
+    let arpa_model = LanguageModel::read_arpa("ngrams_test/model.arpa").unwrap();
+    assert!(arpa_model.validate().is_empty());
+
+    // Break the structure in several ways
+    language_model.levels[1].ngrams.swap(1, 2);
+    language_model.levels[0].offsets = Indices::Narrow(vec![5, 1, 3]);
+    language_model.levels[1].offsets = Indices::Narrow(vec![0, 2, 3, 6]);
+    language_model.levels[2].ngrams[3].0 = 7;
+    let violations = language_model.validate();
+    let correct_violations = vec![
+        Violation::OffsetsNotSorted { order: 1, idx: 0 },
+        Violation::ChildrenNotSorted { order: 1, idx: 1 },
+        Violation::ChildrenOutOfBounds {
+            order: 2,
+            idx: 2,
+            offset: 3,
+            no: 3,
+            len: 4,
+        },
+        Violation::InvalidLabel {
+            order: 3,
+            idx: 3,
+            label: 7,
+        },
+        Violation::InvalidSuffix {
+            order: 3,
+            idx: 0,
+            suffix_idx: 1,
+        },
+        Violation::InvalidSuffix {
+            order: 3,
+            idx: 1,
+            suffix_idx: 2,
+        },
+        Violation::InvalidSuffix {
+            order: 3,
+            idx: 2,
+            suffix_idx: 0,
+        },
+        Violation::InvalidSuffix {
+            order: 3,
+            idx: 3,
+            suffix_idx: 1,
+        },
+    ];
+    assert!(violations == correct_violations);
+
+    // The highest order has no children, so it must not have offsets
+    let mut broken_model = LanguageModel::read_arpa("ngrams_test/model.arpa").unwrap();
+    broken_model.levels[2].offsets = Indices::Narrow(vec![0]);
+    let correct_violations = vec![Violation::OffsetCount {
+        order: 3,
+        no_ngrams: 4,
+        no_offsets: 1,
+    }];
+    assert!(broken_model.validate() == correct_violations);
+
+    // Skipping a line in lenient mode leaves a gap between the children of two bigrams, which
+    // can not be stored
+    let file_bigrams = TempFile::new("validate_2gms.txt");
+    let fname_bigrams = file_bigrams.path();
+    std::fs::write(
+        fname_bigrams,
+        "1 -0.40546507 0 2\n0 x 2 1\n1 -1.0986123 3 1\n",
+    )
+    .unwrap();
+    let options = ReadOptions {
+        mode: ParseMode::Lenient,
+        validate: true,
+    };
+    let result = LanguageModel::read_from_text_with_options(
+        "ngrams_test/symt.txt",
+        "ngrams_test/1gms.txt",
+        fname_bigrams,
+        "ngrams_test/3gms.txt",
+        options,
+    );
+    assert!(matches!(result, Err(Error::Structure(_))));
+
+    // A valid binary file passes the validation
+    let file_bin = TempFile::new("validate.bin");
+    let fname_bin = file_bin.path();
+    let trained_model = LanguageModel::train("a b b\nb a\n").unwrap();
+    trained_model.write(fname_bin).unwrap();
+    let options = ReadOptions {
+        validate: true,
+        ..Default::default()
+    };
+    assert!(LanguageModel::read_with_options(fname_bin, options).unwrap() == trained_model);
+}
+
+#[test]
+/// Test case D15
+/// Serve predictions and transitions from a memory-mapped file
+fn test_mapped_language_model() {
+    let corpus = "the cat sat on the mat\nthe dog sat on the log\na cat and a dog\n";
+    let trained_model = LanguageModel::train(corpus).unwrap();
+    let arpa_model = LanguageModel::read_arpa("ngrams_test/model.arpa").unwrap();
+    let sequences = [
+        vec![
+            "the", "cat", "sat", "on", "a", "mat", "unknown", "the", "dog",
+        ],
+        vec!["a", "b", "b", "a", "a", "b", "c", "b"],
+    ];
+
+    for (idx, language_model) in [trained_model, arpa_model].iter().enumerate() {
+        let file = TempFile::new(&format!("mapped_{}.bin", idx));
+        let fname = file.path();
+        language_model.write_mapped(fname).unwrap();
+        let mapped_model = MappedLanguageModel::open(fname).unwrap();
+        assert!(mapped_model.smoothing() == language_model.smoothing());
+
+        // The mapped model has to take the same transitions and predict the same words
+        let mut lm_state = LMState::default();
+        let mut mapped_state = LMState::default();
+        for symbol in &sequences[idx] {
+            assert!(mapped_model.predict(mapped_state, 3) == language_model.predict(lm_state, 3));
+            assert!(
+                mapped_model.predict_with_prefix(mapped_state, "a", 10)
+                    == language_model.predict_with_prefix(lm_state, "a", 10)
+            );
+            lm_state = language_model.get_next_state(lm_state, symbol);
+            mapped_state = mapped_model.get_next_state(mapped_state, symbol);
+            assert!(mapped_state == lm_state);
+        }
+    }
+
+    // Files in other formats are rejected
+    let result = MappedLanguageModel::open("ngrams_test/trigram_model_v0.bin");
+    assert!(matches!(result, Err(Error::Format { .. })));
+}
+
+#[test]
+/// Test case D16
+/// Build, store and use a language model of a higher order
+fn test_higher_order() {
+    let corpus = "the cat sat on the mat\nthe cat sat on the hat\nthe dog sat on the log\n";
+    let language_model = LanguageModelBuilder::with_order(4)
+        .add_text(corpus)
+        .build()
+        .unwrap();
+    assert!(language_model.order() == 4);
+    assert!(language_model.validate().is_empty());
+
+    // The context grows up to three words
+    let mut lm_state = LMState::default();
+    for (symbol, context_len) in [("the", 1), ("cat", 2), ("sat", 3), ("on", 3), ("the", 3)] {
+        lm_state = language_model.get_next_state(lm_state, symbol);
+        assert!(lm_state.context_len == context_len);
+    }
+    let predictions = language_model.predict(lm_state, 3);
+    let correct_prediction = vec![
+        ("mat", -1.0986123),
+        ("hat", -1.0986123),
+        ("log", -1.0986123),
+    ];
+    assert!(cmp(predictions, correct_prediction));
+
+    // Backoff from a context of three words to its suffix
+    let lm_state = language_model.backoff(lm_state);
+    assert!(lm_state.context_len == 2);
+    let predictions = language_model.predict(lm_state, 1);
+    assert!(cmp(predictions, vec![("mat", -1.0986123)]));
+
+    // The n-grams of every order are used for scoring
+    let score = language_model.score_sequence(&["the", "cat", "sat", "on", "the", "mat"]);
+    let orders: Vec<usize> = score.tokens.iter().map(|token| token.order).collect();
+    assert!(orders == [1, 2, 3, 4, 4, 4]);
+
+    // The model is the same after writing and reading it
+    let file_bin = TempFile::new("higher_order.bin");
+    let fname_bin = file_bin.path();
+    language_model.write(fname_bin).unwrap();
+    assert!(LanguageModel::read(fname_bin).unwrap() == language_model);
+    let file_arpa = TempFile::new("higher_order.arpa");
+    let fname_arpa = file_arpa.path();
+    language_model.write_arpa(fname_arpa).unwrap();
+    let mut arpa_model = LanguageModel::read_arpa(fname_arpa).unwrap();
+    arpa_model.set_smoothing(Smoothing::StupidBackoff);
+    assert!(arpa_model.order() == 4);
+    assert!(cmp(
+        arpa_model.predict(lm_state, 10),
+        language_model.predict(lm_state, 10)
+    ));
+
+    // A model trained with Katz backoff predicts normalized probabilities in every context
+    let language_model = LanguageModelBuilder::with_order(4)
+        .add_text(corpus)
+        .smoothing(Smoothing::Katz)
+        .build()
+        .unwrap();
+    for context in [
+        vec!["the"],
+        vec!["the", "cat", "sat"],
+        vec!["sat", "on", "the"],
+    ] {
+        let mut lm_state = LMState::default();
+        for symbol in context {
+            lm_state = language_model.get_next_state(lm_state, symbol);
+        }
+        let predictions = language_model.predict(lm_state, 8);
+        let total: f32 = predictions.iter().map(|(_, log_prob)| log_prob.exp()).sum();
+        assert!((total - 1.0).abs() < 0.00001);
+    }
+
+    // Files of trigram models written before the order was generalized can still be read
+    let correct_lm = read_test_model();
+    assert!(LanguageModel::read("ngrams_test/trigram_model_v0.bin").unwrap() == correct_lm);
+}
+
+#[test]
+/// Test case D17
+/// Store contexts with more children than the previous layout allowed and convert the children
+/// of the files of trigram models
+fn test_large_contexts() {
+    // The unigram "a" is followed by more words than fit into 16 bits
+    let no_words = 70_000;
+    let corpus: String = (0..no_words).map(|idx| format!("a w{}\n", idx)).collect();
+    let language_model = LanguageModelBuilder::with_order(2)
+        .add_text(&corpus)
+        .build()
+        .unwrap();
+    assert!(language_model.validate().is_empty());
+    let label = language_model.symt.get_index_of("a").unwrap();
+    assert!(language_model.levels[0].children(label).len() == no_words);
+
+    let lm_state = language_model.get_next_state(LMState::default(), "a");
+    assert!(lm_state.context_len == 1);
+    let predictions = language_model.predict_with_prefix(lm_state, "w", 1);
+    assert!(cmp(predictions, vec![("w0", -11.156251)]));
+    // Backing off to the unigram "a" beats every word following it
+    let predictions = language_model.predict(lm_state, 1);
+    assert!(cmp(predictions, vec![("a", (0.5f32 * 0.4).ln())]));
+    let lm_state = language_model.get_next_state(lm_state, "w69999");
+    assert!(lm_state.context_len == 1);
+
+    // The mapped model uses the same children
+    let file_mapped = TempFile::new("large_contexts.lmm");
+    let fname_mapped = file_mapped.path();
+    language_model.write_mapped(fname_mapped).unwrap();
+    let mapped_model = MappedLanguageModel::open(fname_mapped).unwrap();
+    let lm_state = mapped_model.get_next_state(LMState::default(), "a");
+    assert!(cmp(
+        mapped_model.predict_with_prefix(lm_state, "w6999", 3),
+        language_model.predict_with_prefix(lm_state, "w6999", 3)
+    ));
+
+    // Indices that do not fit into 32 bits are stored with 64 bits
+    let narrow: Indices = [0, 3, u32::MAX as usize].into_iter().collect();
+    assert!(matches!(narrow, Indices::Narrow(_)));
+    if let Some(wide_idx) = (u32::MAX as usize).checked_add(1) {
+        let wide: Indices = [0, 3, wide_idx].into_iter().collect();
+        assert!(matches!(wide, Indices::Wide(_)));
+        assert!(wide.get(2) == Some(wide_idx));
+    }
+
+    // The files store the start offsets of the children, so there is no limit either
+    let file_bin = TempFile::new("large_contexts.bin");
+    let fname_bin = file_bin.path();
+    language_model.write(fname_bin).unwrap();
+    assert!(LanguageModel::read(fname_bin).unwrap() == language_model);
+
+    // Files without a header store the number of children of every n-gram, they are converted
+    // when they are read
+    let correct_lm = read_test_model();
+    let language_model = LanguageModel::read("ngrams_test/trigram_model_v0.bin").unwrap();
+    assert!(language_model == correct_lm);
+}
+
+#[test]
+/// Test case D18
+/// Write and check the header of a model file and read files of older versions
+fn test_file_header() {
+    let language_model = read_test_model();
+    let file_bin = TempFile::new("file_header.bin");
+    let fname_bin = file_bin.path();
+    language_model.write(fname_bin).unwrap();
+
+    // The header describes the model
+    let header = LanguageModel::read_header(fname_bin).unwrap();
+    assert!(header.version == FORMAT_VERSION);
+    assert!(header.order == 3);
+    assert!(header.vocabulary_size == 2);
+    assert!(header.smoothing == Smoothing::StupidBackoff);
+    assert!(header.creator.starts_with("language_model "));
+    assert!(header.created > 0);
+
+    // A model whose checksum does not match is rejected
+    let bytes = std::fs::read(fname_bin).unwrap();
+    let header_len = 8 + bincode::serialized_size(&header).unwrap() as usize;
+    let mut corrupted_bytes = bytes.clone();
+    corrupted_bytes[header_len - 1] ^= 0xff;
+    std::fs::write(fname_bin, &corrupted_bytes).unwrap();
+    let result = LanguageModel::read(fname_bin);
+    assert!(matches!(result, Err(Error::Format { .. })));
+
+    // Files of newer versions are rejected
+    let mut newer_bytes = bytes;
+    newer_bytes[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    std::fs::write(fname_bin, &newer_bytes).unwrap();
+    let result = LanguageModel::read(fname_bin);
+    assert!(matches!(result, Err(Error::Format { .. })));
+    let result = LanguageModel::read_header(fname_bin);
+    assert!(matches!(result, Err(Error::Format { .. })));
+
+    // Files written before the header was added have none, but they are converted when they
+    // are read
+    let fname = "ngrams_test/trigram_model_v0.bin";
+    let result = LanguageModel::read_header(fname);
+    assert!(matches!(result, Err(Error::Format { .. })));
+    assert!(LanguageModel::read(fname).unwrap() == language_model);
+
+    // Models of any order are written with their backoff weights and smoothing
+    let katz_model = LanguageModelBuilder::with_order(4)
+        .add_text("the cat sat on the mat\nthe dog sat on the log\n")
+        .smoothing(Smoothing::Katz)
+        .build()
+        .unwrap();
+    katz_model.write(fname_bin).unwrap();
+    assert!(LanguageModel::read(fname_bin).unwrap() == katz_model);
+}
+
+#[test]
+/// Test case D19
+/// Read and write language models from readers and to writers
+fn test_streams() {
+    let language_model = read_test_model();
+
+    // The text files can be read from memory
+    let read_text = |fname| std::fs::read_to_string(fname).unwrap();
+    let (text_model, report) = LanguageModel::read_from_text_readers(
+        read_text("ngrams_test/symt.txt").as_bytes(),
+        read_text("ngrams_test/1gms.txt").as_bytes(),
+        read_text("ngrams_test/2gms.txt").as_bytes(),
+        read_text("ngrams_test/3gms.txt").as_bytes(),
+        ReadOptions::default(),
+    )
+    .unwrap();
+    assert!(report.is_clean());
+    assert!(text_model == language_model);
+    let result = LanguageModel::read_from_text_readers(
+        "a\nb\n".as_bytes(),
+        "-0.6931472 0 1\nx 1 2\n".as_bytes(),
+        "".as_bytes(),
+        "".as_bytes(),
+        ReadOptions::default(),
+    );
+    match result {
+        Err(Error::Parse { fname, line, .. }) => {
+            assert!(fname == "unigrams");
+            assert!(line == 2);
+        }
+        _ => panic!("Expected a parse error"),
+    }
+
+    // A model written to a buffer can be read back from it
+    let mut bytes = Vec::new();
+    language_model.write_to(&mut bytes).unwrap();
+    let header = LanguageModel::read_header_from(&bytes[..]).unwrap();
+    assert!(header.order == 3);
+    assert!(LanguageModel::read_from(&bytes[..]).unwrap() == language_model);
+
+    // An embedded model of an older version is converted
+    let embedded_bytes = include_bytes!("../ngrams_test/trigram_model_v0.bin");
+    assert!(LanguageModel::read_from(&embedded_bytes[..]).unwrap() == language_model);
+    let result = LanguageModel::read_from("not a model".as_bytes());
+    assert!(matches!(result, Err(Error::Format { .. })));
+
+    // The ARPA format can be written to and read from a buffer
+    let mut arpa_bytes = Vec::new();
+    language_model.write_arpa_to(&mut arpa_bytes).unwrap();
+    let arpa_model = LanguageModel::read_arpa_from(&arpa_bytes[..]).unwrap();
+    assert!(arpa_model.symt == language_model.symt);
+    for (level, correct_level) in arpa_model.levels.iter().zip(&language_model.levels) {
+        for (a, b) in level.ngrams.iter().zip(&correct_level.ngrams) {
+            assert!((a.1 - b.1).abs() < 0.00001 && a.0 == b.0);
+        }
+        assert!(level.offsets == correct_level.offsets);
+    }
+}
+
+#[test]
+/// Test case D20
+/// Train a language model with interpolated modified Kneser-Ney smoothing
+fn test_kneser_ney() {
+    let language_model = build_from_corpus(Smoothing::KneserNey);
+    assert!(language_model.validate().is_empty());
+    assert!(language_model.smoothing() == Smoothing::KneserNey);
+
+    // "the" follows two different words and starts four sentences
+    let mut lm_state = LMState::default();
+    let predictions = language_model.predict(lm_state, 1);
+    assert!(cmp(predictions, vec![("the", -1.7917595)]));
+    lm_state = language_model.get_next_state(lm_state, "the");
+    let predictions = language_model.predict_with_prefix(lm_state, "c", 1);
+    assert!(cmp(predictions, vec![("cat", -2.1400662)]));
+    lm_state = language_model.get_next_state(lm_state, "cat");
+    let predictions = language_model.predict(lm_state, 1);
+    assert!(cmp(predictions, vec![("sat", -0.3698330)]));
+
+    // Unseen words get the probability of the shorter context weighted by the backoff weight
+    let score = language_model.score_sequence(&["sat", "on", "the", "fish"]);
+    assert!((score.tokens[3].log_prob - -2.9892176).abs() < 0.00001);
+
+    // The distributions of all contexts are normalized
+    for context in [vec![], vec!["the"], vec!["the", "dog"], vec!["on", "the"]] {
+        let mut lm_state = LMState::default();
+        for symbol in context {
+            lm_state = language_model.get_next_state(lm_state, symbol);
+        }
+        let predictions = language_model.predict(lm_state, 20);
+        let total: f32 = predictions.iter().map(|(_, log_prob)| log_prob.exp()).sum();
+        assert!((total - 1.0).abs() < 0.00001);
+    }
+
+    // The discounts are estimated from the number of n-grams counted one to four times
+    let counts = [1; 10]
+        .into_iter()
+        .chain([2; 5])
+        .chain([3; 3])
+        .chain([4; 2]);
+    let discounts = builder::kneser_ney_discounts(counts.chain([7, 9]));
+    let correct_discounts = [0.5, 1.1, 1.6666667];
+    for (discount, correct_discount) in discounts.iter().zip(correct_discounts) {
+        assert!((discount - correct_discount).abs() < 0.00001);
+    }
+
+    // The same model can be built from the counts of the n-grams
+    let mut counts: HashMap<Vec<&str>, u64> = HashMap::new();
+    for line in CORPUS.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        for len in 1..=3 {
+            for window in words.windows(len) {
+                *counts.entry(window.to_vec()).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut builder = LanguageModelBuilder::new();
+    builder.smoothing(Smoothing::KneserNey);
+    for (words, count) in counts {
+        builder.add_count(&words, count);
+    }
+    let counted_model = builder.build().unwrap();
+    let sentence = ["the", "dog", "sat", "on", "a", "mat"];
+    let score = language_model.score_sequence(&sentence);
+    let counted_score = counted_model.score_sequence(&sentence);
+    assert!((score.log_prob - counted_score.log_prob).abs() < 0.00001);
+
+    // Counts whose last words were not counted are rejected by every smoothing
+    for smoothing in [
+        Smoothing::StupidBackoff,
+        Smoothing::Katz,
+        Smoothing::KneserNey,
+    ] {
+        let result = LanguageModelBuilder::new()
+            .add_count(&["a"], 2)
+            .add_count(&["b"], 1)
+            .add_count(&["c"], 1)
+            .add_count(&["a", "b"], 1)
+            .add_count(&["a", "b", "c"], 1)
+            .smoothing(smoothing)
+            .build();
+        assert!(matches!(result, Err(Error::Structure(_))));
+    }
+}
+
+#[test]
+/// Test case D21
+/// Prune language models by relative entropy and minimum count
+fn test_prune() {
+    let is_normalized = |language_model: &LanguageModel| {
+        for context in [vec![], vec!["the"], vec!["the", "dog"], vec!["on", "the"]] {
+            let mut lm_state = LMState::default();
+            for symbol in context {
+                lm_state = language_model.get_next_state(lm_state, symbol);
+            }
+            let predictions = language_model.predict(lm_state, 20);
+            let total: f32 = predictions.iter().map(|(_, log_prob)| log_prob.exp()).sum();
+            if (total - 1.0).abs() > 0.00001 {
+                return false;
+            }
+        }
+        true
+    };
+
+    for smoothing in [Smoothing::Katz, Smoothing::KneserNey] {
+        let mut language_model = build_from_corpus(smoothing);
+
+        // Some n-grams are removed, the model stays consistent and normalized
+        let report = language_model.prune(0.05).unwrap();
+        assert!(report.ngrams_before == [12, 16, 14]);
+        assert!(report.no_removed() > 0);
+        assert!(report.ngrams_after[0] == 12 && report.ngrams_after[1] > 0);
+        assert!(report.ngrams_after == language_model.no_ngrams());
+        assert!(report.bytes_after < report.bytes_before);
+        assert!(language_model.validate().is_empty());
+        assert!(is_normalized(&language_model));
+
+        // With a high threshold only the unigrams are kept
+        let report = language_model.prune(f64::INFINITY).unwrap();
+        assert!(report.ngrams_after == [12, 0, 0]);
+        assert!(language_model.validate().is_empty());
+        assert!(is_normalized(&language_model));
+        let lm_state = language_model.get_next_state(LMState::default(), "the");
+        assert!(cmp(
+            language_model.predict(lm_state, 3),
+            language_model.predict(LMState::default(), 3)
+        ));
+    }
+
+    // The n-grams counted once are left out when training
+    let language_model = LanguageModelBuilder::new()
+        .add_text(CORPUS)
+        .smoothing(Smoothing::Katz)
+        .min_count(2)
+        .build()
+        .unwrap();
+    assert!(language_model.no_ngrams() == [12, 5, 3]);
+    assert!(language_model.validate().is_empty());
+    assert!(is_normalized(&language_model));
+
+    // A trained model is pruned to the same n-grams with the counts of the corpus
+    let mut counts: HashMap<Vec<&str>, u64> = HashMap::new();
+    for line in CORPUS.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        for len in 1..=3 {
+            for ngram in words.windows(len) {
+                *counts.entry(ngram.to_vec()).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut pruned_model = build_from_corpus(Smoothing::Katz);
+    let report = pruned_model
+        .prune_by_count(|words| counts.get(words).copied().unwrap_or(0), 2)
+        .unwrap();
+    assert!(report.ngrams_before == [12, 16, 14]);
+    assert!(report.ngrams_after == [12, 5, 3]);
+    assert!(pruned_model.validate().is_empty());
+    assert!(is_normalized(&pruned_model));
+    let words = |language_model: &LanguageModel| -> Vec<Vec<Vec<Label>>> {
+        language_model
+            .to_ngrams()
+            .into_iter()
+            .map(|entries| entries.into_iter().map(|(words, _, _)| words).collect())
+            .collect()
+    };
+    assert!(words(&pruned_model) == words(&language_model));
+}
+
+#[test]
+/// Test case D22
+/// Restrict the vocabulary to a whitelist or the most probable words
+fn test_vocabulary() {
+    for smoothing in [
+        Smoothing::StupidBackoff,
+        Smoothing::Katz,
+        Smoothing::KneserNey,
+    ] {
+        let mut language_model = build_from_corpus(smoothing);
+        let original = build_from_corpus(smoothing);
+
+        // Only the n-grams made of the listed words are kept, the unknown words are ignored
+        let report = language_model
+            .restrict_vocabulary(&["the", "cat", "sat", "on", "mat", "unknown"])
+            .unwrap();
+        assert!(report.ngrams_before == [12, 16, 14]);
+        assert!(report.ngrams_after == [5, 5, 4]);
+        assert!(report.bytes_after < report.bytes_before);
+        assert!(language_model.validate().is_empty());
+        let symbols: Vec<&str> = language_model.symt.iter().map(String::as_str).collect();
+        assert!(symbols == ["the", "cat", "sat", "on", "mat"]);
+
+        let lm_state = ["the", "cat"]
+            .iter()
+            .fold(LMState::default(), |lm_state, symbol| {
+                language_model.get_next_state(lm_state, symbol)
+            });
+        let original_state = ["the", "cat"]
+            .iter()
+            .fold(LMState::default(), |lm_state, symbol| {
+                original.get_next_state(lm_state, symbol)
+            });
+        let predictions = language_model.predict(lm_state, 10);
+        assert!(predictions.len() == 5);
+        assert!(predictions[0].0 == "sat");
+        if smoothing == Smoothing::StupidBackoff {
+            // The probabilities of the kept n-grams do not change
+            assert!(cmp(
+                predictions[..1].to_vec(),
+                original.predict(original_state, 1)
+            ));
+        } else {
+            // The distributions are normalized over the smaller vocabulary
+            for lm_state in [LMState::default(), lm_state] {
+                let total: f32 = language_model
+                    .predict(lm_state, 10)
+                    .iter()
+                    .map(|(_, log_prob)| log_prob.exp())
+                    .sum();
+                assert!((total - 1.0).abs() < 0.00001);
+            }
+        }
+
+        // The most probable words are kept
+        let report = language_model.limit_vocabulary(2).unwrap();
+        assert!(report.ngrams_after == [2, 1, 0]);
+        assert!(language_model.validate().is_empty());
+        let symbols: Vec<&str> = language_model.symt.iter().map(String::as_str).collect();
+        assert!(symbols == ["the", "cat"]);
+    }
+}
+
+#[test]
+/// Test case D23
+/// Predict at the start of a sentence, score the end of sentences and read unknown words
+fn test_sentence_boundaries() {
+    let language_model = LanguageModelBuilder::new()
+        .sentence_boundaries(true)
+        .add_text(CORPUS)
+        .add_sentence(&["the", UNKNOWN, "sat"])
+        .build()
+        .unwrap();
+    assert!(language_model.validate().is_empty());
+
+    // Five of the six sentences start with "the"
+    let predictions = language_model.predict(LMState::sentence_start(), 2);
+    assert!(cmp(
+        predictions,
+        vec![("the", (5.0f32 / 6.0).ln()), ("a", (1.0f32 / 6.0).ln())]
+    ));
+    let file = TempFile::new("sentence_boundaries.bin");
+    let fname = file.path();
+    language_model.write_mapped(fname).unwrap();
+    let mapped_model = MappedLanguageModel::open(fname).unwrap();
+    assert!(cmp(
+        mapped_model.predict(LMState::sentence_start(), 2),
+        language_model.predict(LMState::sentence_start(), 2)
+    ));
+
+    // The end of the sentence is scored after the last word
+    let sentence = ["the", "dog", "ate", "a", "bone"];
+    let score = language_model.score_sentence(&sentence);
+    assert!(score.tokens.len() == 6);
+    assert!((score.tokens[0].log_prob - (5.0f32 / 6.0).ln()).abs() < 0.00001);
+    assert!(score.tokens[5].order == 3 && score.tokens[5].log_prob == 0.0);
+    let sequence_score = language_model.score_sequence(&sentence);
+    assert!(sequence_score.tokens.len() == 5 && sequence_score.tokens[0].order == 1);
+    let perplexity = language_model.perplexity("the dog ate a bone\n");
+    assert!((perplexity - (-score.log_prob / 6.0).exp()).abs() < 0.0001);
+
+    // Unknown words are read as <unk>
+    let lm_state = language_model.get_next_state(LMState::sentence_start(), "the");
+    assert!(
+        language_model.get_next_state(lm_state, "zebra")
+            == language_model.get_next_state(lm_state, UNKNOWN)
+    );
+    let score = language_model.score_sentence(&["the", "zebra", "sat"]);
+    let unknown_score = language_model.score_sentence(&["the", UNKNOWN, "sat"]);
+    assert!(score.no_oovs() == 1 && unknown_score.no_oovs() == 0);
+    assert!(score.log_prob == unknown_score.log_prob);
+    assert!(score.tokens[1].order == 3 && score.tokens[2].order == 3);
+
+    // Without the symbols, the sentence start is the initial state and unknown words reset
+    // the context
+    let language_model = build_from_corpus(Smoothing::StupidBackoff);
+    assert!(cmp(
+        language_model.predict(LMState::sentence_start(), 3),
+        language_model.predict(LMState::default(), 3)
+    ));
+    let score = language_model.score_sentence(&["the", "zebra", "sat"]);
+    assert!(score.tokens.len() == 3 && score.no_oovs() == 1 && score.no_scored() == 2);
+    assert!(score.tokens[1].order == 0 && score.tokens[2].order == 1);
+}
+
+#[test]
+/// Test case D24
+/// Only the most probable predictions are collected, they match sorting the whole vocabulary
+fn test_top_predictions() {
+    let language_model = build_from_corpus(Smoothing::StupidBackoff);
+    let file = TempFile::new("top_predictions.bin");
+    let fname = file.path();
+    language_model.write_mapped(fname).unwrap();
+    let mapped_model = MappedLanguageModel::open(fname).unwrap();
+
+    // Sort all unigrams by their probability and the words with the same one by their label
+    let mut unigrams: Vec<(&str, f32)> = language_model.levels[0]
+        .ngrams
+        .iter()
+        .map(|&(label, log_prob)| (language_model.symt[label as usize].as_str(), log_prob))
+        .collect();
+    unigrams.sort_by(|a, b| b.1.total_cmp(&a.1));
+    for max_no_predictions in 0..=unigrams.len() + 1 {
+        let expected = unigrams[..max_no_predictions.min(unigrams.len())].to_vec();
+        let predictions = language_model.predict(LMState::default(), max_no_predictions);
+        assert!(predictions == expected);
+        assert!(mapped_model.predict(LMState::default(), max_no_predictions) == expected);
+    }
+
+    // After "the" the six words following it are predicted, the other words are predicted by
+    // backing off to the unigrams
+    let lm_state = language_model.get_next_state(LMState::default(), "the");
+    let children = language_model.predict(lm_state, 6);
+    assert!(children.len() == 6 && children[0] == ("cat", (2.0f32 / 8.0).ln()));
+    let mut expected = children.clone();
+    expected.extend(
+        unigrams
+            .iter()
+            .filter(|(word, _)| children.iter().all(|(child, _)| child != word))
+            .map(|&(word, log_prob)| (word, log_prob + BACKOFF_WEIGHT)),
+    );
+    expected.sort_by(|a, b| b.1.total_cmp(&a.1));
+    expected.truncate(9);
+    assert!(language_model.predict(lm_state, 9) == expected);
+    assert!(mapped_model.predict(lm_state, 9) == expected);
+
+    // With a prefix only the words starting with it are predicted
+    let predictions = language_model.predict_with_prefix(lm_state, "b", 3);
+    assert!(cmp(
+        predictions,
+        vec![("bone", (1.0f32 / 28.0).ln() + BACKOFF_WEIGHT)]
+    ));
+}
+
+#[test]
+/// Test case D25
+/// Predict labels into a buffer or lazily and translate between labels and symbols
+fn test_label_predictions() {
+    let language_model = build_from_corpus(Smoothing::StupidBackoff);
+    let file = TempFile::new("label_predictions.bin");
+    let fname = file.path();
+    language_model.write_mapped(fname).unwrap();
+    let mapped_model = MappedLanguageModel::open(fname).unwrap();
+
+    // The labels are the indices of the symbol table
+    assert!(language_model.label("cat") == Some(1) && mapped_model.label("cat") == Some(1));
+    assert!(language_model.symbol(1) == Some("cat") && mapped_model.symbol(1) == Some("cat"));
+    assert!(language_model.label("zebra").is_none() && mapped_model.label("zebra").is_none());
+    assert!(language_model.symbol(12).is_none() && mapped_model.symbol(12).is_none());
+
+    // The predictions written into the buffer are the ones of predict
+    let lm_state = language_model.get_next_state(LMState::default(), "the");
+    let mut predictions = Vec::new();
+    language_model.predict_labels(lm_state, 5, &mut predictions);
+    let symbols: Vec<(&str, f32)> = predictions
+        .iter()
+        .map(|&(label, log_prob)| (language_model.symbol(label).unwrap(), log_prob))
+        .collect();
+    assert!(symbols == language_model.predict(lm_state, 5));
+    let capacity = predictions.capacity();
+    mapped_model.predict_labels(lm_state, 5, &mut predictions);
+    assert!(predictions.len() == 5 && predictions.capacity() == capacity);
+    assert!(symbols == mapped_model.predict(lm_state, 5));
+    language_model.predict_labels_with_prefix(lm_state, "h", 5, &mut predictions);
+    assert!(predictions == [(5, (1.0f32 / 8.0).ln())]);
+
+    // The iterator yields every word once, sorting it gives all predictions
+    for lm_state in [LMState::default(), lm_state] {
+        let mut all_predictions: Vec<(Label, LogProb)> =
+            language_model.iter_label_predictions(lm_state).collect();
+        assert!(all_predictions.len() == 12);
+        assert!(mapped_model
+            .iter_label_predictions(lm_state)
+            .eq(all_predictions.iter().copied()));
+        all_predictions.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        language_model.predict_labels(lm_state, 12, &mut predictions);
+        assert!(all_predictions == predictions);
+    }
+
+    // The words following "the" come first in the order of their label
+    let first: Vec<&str> = language_model
+        .iter_label_predictions(lm_state)
+        .take(6)
+        .map(|(label, _)| language_model.symbol(label).unwrap())
+        .collect();
+    assert!(first == ["cat", "mat", "hat", "dog", "log", "fish"]);
+}
+
+#[test]
+/// Test case D26
+/// Convert models with a configuration, the compression level, the validation and the
+/// progress callback
+fn test_convert_config() {
+    let file_arpa = TempFile::new("convert.arpa");
+    let fname_arpa = file_arpa.path();
+    let file_fast = TempFile::new("convert_fast.bin");
+    let fname_fast = file_fast.path();
+    let file_best = TempFile::new("convert_best.bin");
+    let fname_best = file_best.path();
+    let language_model =
+        LanguageModel::train("the cat sat on the mat\nthe dog sat on the log").unwrap();
+    language_model.write_arpa(fname_arpa).unwrap();
+
+    // Every step is reported in order, the validation is skipped if it is not requested
+    let mut steps = Vec::new();
+    let mut config = ConvertConfig::new(ConvertInput::Arpa(fname_arpa.to_string()), fname_fast);
+    config.compression_level = 0;
+    config.progress = Some(Box::new(|step| steps.push(step)));
+    let report = LanguageModel::convert(config).unwrap();
+    assert!(report.is_clean());
+    assert!(
+        steps
+            == [
+                ConvertStep::Reading,
+                ConvertStep::Writing,
+                ConvertStep::Finished
+            ]
+    );
+
+    let mut steps = Vec::new();
+    let mut config = ConvertConfig::new(ConvertInput::Binary(fname_fast.to_string()), fname_best);
+    config.compression_level = 9;
+    config.validate = true;
+    config.progress = Some(Box::new(|step| steps.push(step)));
+    LanguageModel::convert(config).unwrap();
+    assert!(
+        steps
+            == [
+                ConvertStep::Reading,
+                ConvertStep::Validating,
+                ConvertStep::Writing,
+                ConvertStep::Finished
+            ]
+    );
+
+    // The level only changes the size of the file, not the model
+    let size = |fname: &str| std::fs::metadata(fname).unwrap().len();
+    assert!(size(fname_best) < size(fname_fast));
+    let fast_model = LanguageModel::read(fname_fast).unwrap();
+    let best_model = LanguageModel::read(fname_best).unwrap();
+    assert!(fast_model.to_ngrams() == best_model.to_ngrams());
+    assert!(fast_model.order() == language_model.order());
+
+    // A level out of range is rejected before anything is read
+    let mut config = ConvertConfig::new(ConvertInput::Arpa(fname_arpa.to_string()), fname_best);
+    config.compression_level = 10;
+    assert!(matches!(
+        LanguageModel::convert(config),
+        Err(Error::Config(_))
+    ));
+    let config = ConvertConfig::new(ConvertInput::Binary("missing.bin".to_string()), fname_best);
+    assert!(LanguageModel::convert(config).is_err());
+
+    // The conversion function of the first release still converts the test folder
+    #[allow(deprecated)]
+    let result = convert_text_to_cmprssd_bin(true);
+    assert!(result.is_ok());
+    assert!(LanguageModel::read("ngrams_test/language_model.bin").unwrap() == read_test_model());
+}
+
+#[test]
+/// Test case D27
+/// Get the statistics of a model
+fn test_stats() {
+    let language_model = LanguageModel::train("a b a c").unwrap();
+    let stats = language_model.stats();
+    assert!(stats.vocabulary_size == 3 && stats.smoothing == Smoothing::StupidBackoff);
+    assert!(stats.symt_bytes == 3);
+    let no_ngrams: Vec<usize> = stats.orders.iter().map(|order| order.no_ngrams).collect();
+    assert!(no_ngrams == language_model.no_ngrams() && no_ngrams == [3, 3, 2]);
+    assert!(stats.total_ngrams() == 8);
+    assert!(stats.total_bytes() == language_model.memory_size());
+
+    // "a" is followed by "b" and "c", "b" only by "a" and "c" by nothing
+    let unigrams = &stats.orders[0];
+    assert!(unigrams.order == 1 && unigrams.no_contexts == 2 && unigrams.max_fan_out == 2);
+    assert!((unigrams.average_fan_out - 1.5).abs() < 1e-9);
+    let bigrams = &stats.orders[1];
+    assert!(bigrams.no_contexts == 2 && bigrams.max_fan_out == 1);
+    let trigrams = &stats.orders[2];
+    assert!(trigrams.no_contexts == 0 && trigrams.average_fan_out == 0.0);
+    assert!(trigrams.offsets_bytes == 0 && trigrams.ngrams_bytes == 2 * 8);
+
+    // The unigram probabilities are 1/2, 1/4 and 1/4
+    let log_probs = &unigrams.log_probs;
+    assert!((log_probs.max - 0.5f32.ln()).abs() < 1e-6);
+    assert!((log_probs.min - 0.25f32.ln()).abs() < 1e-6);
+    assert!((log_probs.mean - (0.5f64.ln() + 2.0 * 0.25f64.ln()) / 3.0).abs() < 1e-6);
+    assert!(log_probs.histogram[..3] == [1, 2, 0]);
+    assert!(trigrams.log_probs.histogram[0] == 2 && trigrams.log_probs.max == 0.0);
+
+    // The printed form has a section for each order
+    let printed = stats.to_string();
+    assert!(printed.contains("Vocabulary size: 3") && printed.contains("3-grams: 2"));
+    assert!(printed.contains("fan-out: 1.50 on average, 2 at most over 2 contexts"));
+    assert!(printed.contains("    (-2, -1]: 2\n"));
+
+    // The last bucket of the histogram holds all lower log probabilities
+    let mut stats = stats;
+    stats.orders[0].log_probs.histogram[19] = 1;
+    assert!(stats.to_string().contains("    ≤ -19: 1\n"));
+    assert!(LogProbStats::default().histogram.is_empty());
+}
+
+#[test]
+/// Test case D28
+/// Look up the log probabilities of stored n-grams and iterate over all of them
+fn test_stored_ngrams() {
+    let language_model = LanguageModel::train("a b a c").unwrap();
+    let file_mapped = TempFile::new("stored_ngrams.lmm");
+    let fname_mapped = file_mapped.path();
+    language_model.write_mapped(fname_mapped).unwrap();
+    let mapped_model = MappedLanguageModel::open(fname_mapped).unwrap();
+
+    // The stored probabilities are returned without backing off
+    for words in [&["a"][..], &["a", "b"], &["a", "b", "a"]] {
+        let log_prob = language_model.ngram_log_prob(words);
+        assert!(log_prob.is_some() && log_prob == mapped_model.ngram_log_prob(words));
+    }
+    assert!(language_model.ngram_log_prob(&["b"]) == Some(0.25f32.ln()));
+    assert!(language_model.ngram_log_prob(&["a", "c"]) == Some(0.5f32.ln()));
+    assert!(language_model.ngram_log_prob(&["b", "a", "c"]) == Some(0.0));
+    for words in [
+        &[][..],
+        &["b", "c"],
+        &["c", "a"],
+        &["zebra"],
+        &["a", "b", "a", "c"],
+    ] {
+        assert!(language_model.ngram_log_prob(words).is_none());
+        assert!(mapped_model.ngram_log_prob(words).is_none());
+    }
+
+    // Each n-gram is followed by its children
+    let ngrams: Vec<(Vec<&str>, LogProb)> = language_model.iter_ngrams().collect();
+    let words: Vec<String> = ngrams.iter().map(|(words, _)| words.join(" ")).collect();
+    assert!(words == ["a", "a b", "a b a", "a c", "b", "b a", "b a c", "c"]);
+    assert!(ngrams.len() == language_model.no_ngrams().iter().sum::<usize>());
+    for (words, log_prob) in &ngrams {
+        assert!(language_model.ngram_log_prob(words) == Some(*log_prob));
+    }
+    assert!(mapped_model.iter_ngrams().eq(ngrams.iter().cloned()));
+}
+
+#[test]
+/// Test case D29
+/// The most probable predictions of every state match sorting the predictions of the whole
+/// vocabulary, also when a word predicted after backing off beats the words of the context
+fn test_top_predictions_match_full_sort() {
+    let corpus = format!("{}bone dog cat the a\n", CORPUS);
+    for order in 2..=4 {
+        for smoothing in [
+            Smoothing::StupidBackoff,
+            Smoothing::Katz,
+            Smoothing::KneserNey,
+        ] {
+            let language_model = LanguageModelBuilder::with_order(order)
+                .add_text(&corpus)
+                .smoothing(smoothing)
+                .build()
+                .unwrap();
+            let contexts: Vec<Vec<&str>> = language_model
+                .iter_ngrams()
+                .map(|(words, _)| words)
+                .filter(|words| words.len() < order)
+                .chain([vec![]])
+                .collect();
+            for context in contexts {
+                let lm_state = context.iter().fold(LMState::default(), |lm_state, word| {
+                    language_model.get_next_state(lm_state, word)
+                });
+                let mut all_predictions: Vec<(Label, LogProb)> =
+                    language_model.iter_label_predictions(lm_state).collect();
+                all_predictions.sort_by(by_log_prob);
+                let mut predictions = Vec::new();
+                for max_no_predictions in 1..=5 {
+                    language_model.predict_labels(lm_state, max_no_predictions, &mut predictions);
+                    assert!(predictions == all_predictions[..max_no_predictions]);
+
+                    let symbol = |label| language_model.symbol(label).unwrap();
+                    let expected: Vec<(&str, LogProb)> = all_predictions
+                        .iter()
+                        .filter(|(label, _)| symbol(*label).starts_with('b'))
+                        .take(max_no_predictions)
+                        .map(|&(label, log_prob)| (symbol(label), log_prob))
+                        .collect();
+                    let predictions =
+                        language_model.predict_with_prefix(lm_state, "b", max_no_predictions);
+                    assert!(predictions == expected);
+                }
+            }
+        }
     }
 }
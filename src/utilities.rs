@@ -1,161 +1,440 @@
 use std::fs::File;
 use std::io::Lines;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::str::{FromStr, SplitWhitespace};
 
 use super::*;
 
-struct LinesIterator {
-    lines: Lines<BufReader<File>>,
+/// How lines that can not be parsed are handled
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ParseMode {
+    /// Every problem is an error
+    #[default]
+    Strict,
+    /// Unexpected tokens at the end of a line are ignored and lines that can not be parsed are
+    /// skipped. Each problem is collected as a warning
+    Lenient,
 }
 
-impl LinesIterator {
-    fn new(filename: &str) -> Self {
+/// The problems found while reading a file in lenient mode
+#[derive(Debug, Default)]
+pub struct ParseReport {
+    /// Each warning is an `Error::Parse` describing the problem and where it was found
+    pub warnings: Vec<Error>,
+}
+
+impl ParseReport {
+    /// True if no problems were found
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+struct LinesIterator<R> {
+    // Name of the file or reader used in the errors
+    fname: String,
+    mode: ParseMode,
+    // Number of the last line returned, starting at one
+    line_no: usize,
+    lines: Lines<BufReader<R>>,
+    // Set after an I/O error, reading does not continue after it
+    failed: bool,
+    warnings: Vec<Error>,
+}
+
+impl LinesIterator<File> {
+    fn new(filename: &str, mode: ParseMode) -> Result<Self, Error> {
         // Open the file in read-only mode.
-        let file = File::open(filename).unwrap();
-        let buf_reader = BufReader::new(file);
-        let lines = buf_reader.lines();
-        LinesIterator { lines }
+        let file = File::open(filename)?;
+        Ok(Self::from_reader(file, filename, mode))
     }
 }
 
-impl Iterator for LinesIterator {
-    type Item = String;
-    fn next(&mut self) -> Option<String> {
-        if let Some(Ok(line)) = self.lines.next() {
-            Some(line)
-        } else {
-            None
+impl<R: Read> LinesIterator<R> {
+    fn from_reader(reader: R, name: &str, mode: ParseMode) -> Self {
+        let buf_reader = BufReader::new(reader);
+        let lines = buf_reader.lines();
+        LinesIterator {
+            fname: name.to_string(),
+            mode,
+            line_no: 0,
+            lines,
+            failed: false,
+            warnings: Vec::new(),
         }
     }
-}
 
-pub struct SymtIterator {
-    lines_iterator: LinesIterator,
-}
+    /// Create an error for the last line returned
+    fn parse_error(&self, column: usize, message: String) -> Error {
+        Error::Parse {
+            fname: self.fname.clone(),
+            line: self.line_no,
+            column,
+            message,
+        }
+    }
 
-impl SymtIterator {
-    pub fn new(filename: &str) -> Self {
-        Self {
-            lines_iterator: LinesIterator::new(filename),
+    /// Report a problem with the last line returned
+    /// In strict mode it is an error, in lenient mode it is collected as a warning
+    fn report(&mut self, error: Error) -> Result<(), Error> {
+        match self.mode {
+            ParseMode::Strict => Err(error),
+            ParseMode::Lenient => {
+                self.warnings.push(error);
+                Ok(())
+            }
+        }
+    }
+
+    /// Parse the next token of the last line returned
+    /// The name of the token is used in the error message
+    fn parse_next<T: FromStr>(&self, tokens: &mut Tokens, name: &str) -> Result<T, Error> {
+        match tokens.next() {
+            Some(text) => text.parse::<T>().map_err(|_| {
+                self.parse_error(tokens.column, format!("Invalid {} \"{}\"", name, text))
+            }),
+            None => Err(self.parse_error(tokens.column, format!("Missing {}", name))),
+        }
+    }
+
+    /// Check that there are no more tokens in the last line returned
+    fn parse_end(&mut self, tokens: &mut Tokens) -> Result<(), Error> {
+        // There should not be more tokens in the line
+        // If there are, there most likely is a problem
+        if let Some(text) = tokens.next() {
+            let error = self.parse_error(tokens.column, format!("Unexpected token \"{}\"", text));
+            self.report(error)?;
         }
+        Ok(())
+    }
+
+    /// Parse the next line with the provided function
+    /// Lines that can not be parsed are skipped in lenient mode
+    fn next_parsed<T>(
+        &mut self,
+        parse: fn(&mut Self, &str) -> Result<T, Error>,
+    ) -> Option<Result<T, Error>> {
+        // If the end of the file was reached, return None
+        while let Some(line) = self.next() {
+            let parsed = line.and_then(|line| parse(self, &line));
+            match parsed {
+                Ok(item) => return Some(Ok(item)),
+                Err(error @ Error::Parse { .. }) => {
+                    if let Err(error) = self.report(error) {
+                        return Some(Err(error));
+                    }
+                }
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        None
     }
 }
 
-impl Iterator for SymtIterator {
-    type Item = Symbol;
+impl<R: Read> Iterator for LinesIterator<R> {
+    type Item = Result<String, Error>;
     fn next(&mut self) -> Option<Self::Item> {
-        // If the end of the file was reached, return None
-        if let Some(line) = self.lines_iterator.next() {
-            let symbol = line.trim().parse::<Symbol>().unwrap();
-            Some(symbol)
-        } else {
-            None
+        if self.failed {
+            return None;
+        }
+        let line = self.lines.next()?;
+        self.line_no += 1;
+        match line {
+            Ok(line) => Some(Ok(line)),
+            // A line that is not valid UTF-8 can be skipped, other errors end the iteration
+            Err(error) if error.kind() == ErrorKind::InvalidData => {
+                Some(Err(self.parse_error(1, "Invalid UTF-8".to_string())))
+            }
+            Err(error) => {
+                self.failed = true;
+                Some(Err(Error::Io(error)))
+            }
         }
     }
 }
 
-pub struct UnigramIterator {
-    lines_iterator: LinesIterator,
+/// The whitespace separated tokens of a line
+struct Tokens<'a> {
+    line: &'a str,
+    split: SplitWhitespace<'a>,
+    // Column of the last token returned or of the end of the line, starting at one
+    column: usize,
 }
 
-impl UnigramIterator {
-    pub fn new(filename: &str) -> Self {
+impl<'a> Tokens<'a> {
+    fn new(line: &'a str) -> Self {
         Self {
-            lines_iterator: LinesIterator::new(filename),
+            line,
+            split: line.split_whitespace(),
+            column: 1,
         }
     }
 }
 
-impl Iterator for UnigramIterator {
-    type Item = Unigram;
-    fn next(&mut self) -> Option<Self::Item> {
-        // If the end of the file was reached, return None
-        if let Some(line) = self.lines_iterator.next() {
-            let mut token = line.split_whitespace();
-            let log_prob = token.next().unwrap().trim().parse::<LogProb>().unwrap();
-            let offset = token.next().unwrap().trim().parse::<Offset>().unwrap();
-            let no_of_ngrams = token.next().unwrap().trim().parse::<NoOfNgrams>().unwrap();
-
-            // There should not be more tokens in the line
-            // If there are, there most likely is a problem
-            if token.next().is_some() {
-                return None;
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
+    fn next(&mut self) -> Option<&'a str> {
+        match self.split.next() {
+            Some(token) => {
+                // The token is a slice of the line, so its position can be computed from the
+                // pointers
+                let byte_offset = token.as_ptr() as usize - self.line.as_ptr() as usize;
+                self.column = self.line[..byte_offset].chars().count() + 1;
+                Some(token)
+            }
+            None => {
+                self.column = self.line.chars().count() + 1;
+                None
+            }
+        }
+    }
+}
+
+/// Implement the constructors and accessors of an iterator over the parsed lines of a text file,
+/// which wraps a `LinesIterator` in its `lines_iterator` field
+macro_rules! impl_lines_reader {
+    ($iterator:ident) => {
+        impl $iterator {
+            /// Read the lines of the file, every problem is an error
+            pub fn new(filename: &str) -> Result<Self, Error> {
+                Self::with_mode(filename, ParseMode::Strict)
+            }
+
+            /// Read the lines of the file, the mode decides how problems are handled
+            pub fn with_mode(filename: &str, mode: ParseMode) -> Result<Self, Error> {
+                Ok(Self {
+                    lines_iterator: LinesIterator::new(filename, mode)?,
+                })
+            }
+        }
+
+        impl<R: Read> $iterator<R> {
+            /// Read the lines from a reader instead of a file
+            /// The name is used in the errors in place of the name of a file
+            pub fn from_reader(reader: R, name: &str, mode: ParseMode) -> Self {
+                Self {
+                    lines_iterator: LinesIterator::from_reader(reader, name, mode),
+                }
             }
 
-            Some((log_prob, offset, no_of_ngrams))
-        } else {
-            None
+            /// Get the problems found so far in lenient mode
+            pub fn warnings(&mut self) -> Vec<Error> {
+                std::mem::take(&mut self.lines_iterator.warnings)
+            }
         }
+    };
+}
+
+pub struct SymtIterator<R = File> {
+    lines_iterator: LinesIterator<R>,
+}
+
+impl_lines_reader!(SymtIterator);
+
+impl<R: Read> Iterator for SymtIterator<R> {
+    type Item = Result<Symbol, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lines_iterator
+            .next_parsed(|_, line| Ok(line.trim().to_string()))
     }
 }
 
-pub struct BigramIterator {
-    lines_iterator: LinesIterator,
+// The lines of the text files of a trigram model
+// A unigram is given by its log probability and the offset and number of its bigrams, its label
+// is its line number. A trigram links to its suffix instead of children
+type Unigram = (LogProb, usize, usize);
+type Bigram = (Label, LogProb, usize, usize);
+type Trigram = (Label, LogProb, usize);
+
+pub struct UnigramIterator<R = File> {
+    lines_iterator: LinesIterator<R>,
 }
 
-impl BigramIterator {
-    pub fn new(filename: &str) -> Self {
-        Self {
-            lines_iterator: LinesIterator::new(filename),
-        }
+impl_lines_reader!(UnigramIterator);
+
+fn parse_unigram<R: Read>(lines: &mut LinesIterator<R>, line: &str) -> Result<Unigram, Error> {
+    let mut tokens = Tokens::new(line);
+    let log_prob = lines.parse_next(&mut tokens, "log probability")?;
+    let offset = lines.parse_next(&mut tokens, "offset")?;
+    let no_of_ngrams = lines.parse_next(&mut tokens, "number of n-grams")?;
+    lines.parse_end(&mut tokens)?;
+    Ok((log_prob, offset, no_of_ngrams))
+}
+
+impl<R: Read> Iterator for UnigramIterator<R> {
+    type Item = Result<Unigram, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lines_iterator.next_parsed(parse_unigram)
     }
 }
 
-impl Iterator for BigramIterator {
-    type Item = Bigram;
+pub struct BigramIterator<R = File> {
+    lines_iterator: LinesIterator<R>,
+}
+
+impl_lines_reader!(BigramIterator);
+
+fn parse_bigram<R: Read>(lines: &mut LinesIterator<R>, line: &str) -> Result<Bigram, Error> {
+    let mut tokens = Tokens::new(line);
+    let label = lines.parse_next(&mut tokens, "label")?;
+    let log_prob = lines.parse_next(&mut tokens, "log probability")?;
+    let offset = lines.parse_next(&mut tokens, "offset")?;
+    let no_of_ngrams = lines.parse_next(&mut tokens, "number of n-grams")?;
+    lines.parse_end(&mut tokens)?;
+    Ok((label, log_prob, offset, no_of_ngrams))
+}
+
+impl<R: Read> Iterator for BigramIterator<R> {
+    type Item = Result<Bigram, Error>;
     fn next(&mut self) -> Option<Self::Item> {
-        // If the end of the file was reached, return None
-        if let Some(line) = self.lines_iterator.next() {
-            let mut token = line.split_whitespace();
-            let label = token.next().unwrap().trim().parse::<Label>().unwrap();
-            let log_prob = token.next().unwrap().trim().parse::<LogProb>().unwrap();
-            let offset = token.next().unwrap().trim().parse::<Offset>().unwrap();
-            let no_of_ngrams = token.next().unwrap().trim().parse::<NoOfNgrams>().unwrap();
-
-            // There should not be more tokens in the line
-            // If there are, there most likely is a problem
-            if token.next().is_some() {
-                return None;
-            }
+        self.lines_iterator.next_parsed(parse_bigram)
+    }
+}
 
-            Some((label, log_prob, offset, no_of_ngrams))
-        } else {
-            None
-        }
+pub struct TrigramIterator<R = File> {
+    lines_iterator: LinesIterator<R>,
+}
+
+impl_lines_reader!(TrigramIterator);
+
+fn parse_trigram<R: Read>(lines: &mut LinesIterator<R>, line: &str) -> Result<Trigram, Error> {
+    let mut tokens = Tokens::new(line);
+    let label = lines.parse_next(&mut tokens, "label")?;
+    let log_prob = lines.parse_next(&mut tokens, "log probability")?;
+    let offset = lines.parse_next(&mut tokens, "offset")?;
+    lines.parse_end(&mut tokens)?;
+    Ok((label, log_prob, offset))
+}
+
+impl<R: Read> Iterator for TrigramIterator<R> {
+    type Item = Result<Trigram, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lines_iterator.next_parsed(parse_trigram)
     }
 }
 
-pub struct TrigramIterator {
-    lines_iterator: LinesIterator,
+/// An n-gram of an ARPA file given by its words, its log10 probability and its optional log10
+/// backoff weight
+pub type ArpaNgram = (Vec<Symbol>, LogProb, Option<LogProb>);
+
+pub struct ArpaIterator<R = File> {
+    lines_iterator: LinesIterator<R>,
+    // Order of the n-grams in the current section. It is zero while reading the header
+    order: usize,
+    // Number of n-grams of each order declared in the header, starting with the unigrams
+    declared_counts: Vec<usize>,
+}
+
+impl ArpaIterator {
+    pub fn new(filename: &str) -> Result<Self, Error> {
+        Ok(Self {
+            lines_iterator: LinesIterator::new(filename, ParseMode::Strict)?,
+            order: 0,
+            declared_counts: Vec::new(),
+        })
+    }
 }
 
-impl TrigramIterator {
-    pub fn new(filename: &str) -> Self {
+impl<R: Read> ArpaIterator<R> {
+    /// Read the lines from a reader instead of a file
+    /// The name is used in the errors in place of the name of a file
+    pub fn from_reader(reader: R, name: &str) -> Self {
         Self {
-            lines_iterator: LinesIterator::new(filename),
+            lines_iterator: LinesIterator::from_reader(reader, name, ParseMode::Strict),
+            order: 0,
+            declared_counts: Vec::new(),
+        }
+    }
+
+    /// Get the number of n-grams of each order declared in the header of the file, starting with
+    /// the unigrams. Orders the header does not list have a count of zero
+    pub fn declared_counts(&self) -> &[usize] {
+        &self.declared_counts
+    }
+
+    /// Parse a line of the file. Lines that do not contain an n-gram are skipped
+    fn parse_line(&mut self, line: &str) -> Result<Option<ArpaNgram>, Error> {
+        let trimmed_line = line.trim();
+        if trimmed_line.is_empty() {
+            return Ok(None);
+        }
+        // Lines starting with a backslash mark the start of a section or the end of the file
+        if trimmed_line.starts_with('\\') {
+            if let Some(order) = trimmed_line
+                .strip_prefix('\\')
+                .and_then(|line| line.strip_suffix("-grams:"))
+            {
+                self.order = order.parse::<usize>().map_err(|_| {
+                    let message = format!("Invalid section \"{}\"", trimmed_line);
+                    self.lines_iterator.parse_error(1, message)
+                })?;
+            }
+            return Ok(None);
         }
+        // The header only contains the number of n-grams of each order
+        if self.order == 0 {
+            if let Some(count) = trimmed_line.strip_prefix("ngram ") {
+                self.parse_declared_count(count)?;
+            }
+            return Ok(None);
+        }
+
+        let lines = &mut self.lines_iterator;
+        let mut tokens = Tokens::new(line);
+        let log_prob = lines.parse_next(&mut tokens, "log probability")?;
+        let mut words = Vec::with_capacity(self.order);
+        for _ in 0..self.order {
+            words.push(lines.parse_next::<Symbol>(&mut tokens, "word")?);
+        }
+        let backoff = match tokens.next() {
+            Some(text) => Some(text.parse::<LogProb>().map_err(|_| {
+                let message = format!("Invalid backoff weight \"{}\"", text);
+                lines.parse_error(tokens.column, message)
+            })?),
+            None => None,
+        };
+        lines.parse_end(&mut tokens)?;
+
+        Ok(Some((words, log_prob, backoff)))
+    }
+
+    /// Parse the number of n-grams of an order given as "order=count" in the header
+    fn parse_declared_count(&mut self, text: &str) -> Result<(), Error> {
+        let parse_error = || {
+            let message = format!("Invalid number of n-grams \"{}\"", text);
+            self.lines_iterator.parse_error(1, message)
+        };
+        let (order, count) = text.split_once('=').ok_or_else(parse_error)?;
+        let order = order.trim().parse::<usize>().map_err(|_| parse_error())?;
+        let count = count.trim().parse::<usize>().map_err(|_| parse_error())?;
+        if order == 0 {
+            return Err(parse_error());
+        }
+        if self.declared_counts.len() < order {
+            self.declared_counts.resize(order, 0);
+        }
+        self.declared_counts[order - 1] = count;
+        Ok(())
     }
 }
 
-impl Iterator for TrigramIterator {
-    type Item = Trigram;
+impl<R: Read> Iterator for ArpaIterator<R> {
+    type Item = Result<ArpaNgram, Error>;
     fn next(&mut self) -> Option<Self::Item> {
-        // If the end of the file was reached, return None
-        if let Some(line) = self.lines_iterator.next() {
-            let mut token = line.split_whitespace();
-            let label = token.next().unwrap().trim().parse::<Label>().unwrap();
-            let log_prob = token.next().unwrap().trim().parse::<LogProb>().unwrap();
-            let offset = token.next().unwrap().trim().parse::<Offset>().unwrap();
-
-            // There should not be more tokens in the line
-            // If there are, there most likely is a problem
-            if token.next().is_some() {
+        while let Some(line) = self.lines_iterator.next() {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => return Some(Err(error)),
+            };
+            if line.trim() == "\\end\\" {
                 return None;
             }
-
-            Some((label, log_prob, offset))
-        } else {
-            None
+            match self.parse_line(&line) {
+                Ok(None) => continue,
+                result => return result.transpose(),
+            }
         }
+        // The end of the file was reached
+        None
     }
 }
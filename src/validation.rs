@@ -0,0 +1,306 @@
+use std::{fmt, ops::Range};
+
+use super::*;
+
+/// A violation of the structure of a language model
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Violation {
+    /// The number of unigrams differs from the number of symbols
+    UnigramCount {
+        no_symbols: usize,
+        no_unigrams: usize,
+    },
+    /// The number of suffix links differs from the number of n-grams of that order
+    SuffixCount {
+        order: usize,
+        no_ngrams: usize,
+        no_suffixes: usize,
+    },
+    /// The number of offsets of the children differs from the number of n-grams of that order
+    /// plus one, or there are offsets for the highest order
+    OffsetCount {
+        order: usize,
+        no_ngrams: usize,
+        no_offsets: usize,
+    },
+    /// The number of backoff weights differs from the number of n-grams of that order
+    BackoffCount {
+        order: usize,
+        no_ngrams: usize,
+        no_backoffs: usize,
+    },
+    /// The children of an n-gram do not fit into the vec of the next order
+    ChildrenOutOfBounds {
+        order: usize,
+        idx: usize,
+        offset: usize,
+        no: usize,
+        len: usize,
+    },
+    /// The children of an n-gram end before they start
+    OffsetsNotSorted { order: usize, idx: usize },
+    /// The children of an n-gram are not sorted strictly by their label
+    ChildrenNotSorted { order: usize, idx: usize },
+    /// The label of an n-gram is not in the symbol table or the label of a unigram is not its
+    /// index
+    InvalidLabel {
+        order: usize,
+        idx: usize,
+        label: Label,
+    },
+    /// An n-gram does not link to the n-gram made of all but its first word
+    InvalidSuffix {
+        order: usize,
+        idx: usize,
+        suffix_idx: usize,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::UnigramCount {
+                no_symbols,
+                no_unigrams,
+            } => write!(
+                f,
+                "There are {} symbols but {} unigrams",
+                no_symbols, no_unigrams
+            ),
+            Violation::SuffixCount {
+                order,
+                no_ngrams,
+                no_suffixes,
+            } => write!(
+                f,
+                "There are {} {}-grams but {} suffix links",
+                no_ngrams, order, no_suffixes
+            ),
+            Violation::OffsetCount {
+                order,
+                no_ngrams,
+                no_offsets,
+            } => write!(
+                f,
+                "There are {} {}-grams but {} offsets of children",
+                no_ngrams, order, no_offsets
+            ),
+            Violation::BackoffCount {
+                order,
+                no_ngrams,
+                no_backoffs,
+            } => write!(
+                f,
+                "There are {} {}-grams but {} backoff weights",
+                no_ngrams, order, no_backoffs
+            ),
+            Violation::ChildrenOutOfBounds {
+                order,
+                idx,
+                offset,
+                no,
+                len,
+            } => write!(
+                f,
+                "The {} children of {}-gram {} at offset {} exceed the {} {}-grams",
+                no,
+                order,
+                idx,
+                offset,
+                len,
+                order + 1
+            ),
+            Violation::OffsetsNotSorted { order, idx } => write!(
+                f,
+                "The children of {}-gram {} end before they start",
+                order, idx
+            ),
+            Violation::ChildrenNotSorted { order, idx } => write!(
+                f,
+                "The children of {}-gram {} are not sorted by their label",
+                order, idx
+            ),
+            Violation::InvalidLabel { order, idx, label } => write!(
+                f,
+                "The label {} of {}-gram {} is not valid",
+                label, order, idx
+            ),
+            Violation::InvalidSuffix {
+                order,
+                idx,
+                suffix_idx,
+            } => write!(
+                f,
+                "{}-gram {} links to {}-gram {}, which is not made of all but its first word",
+                order,
+                idx,
+                order - 1,
+                suffix_idx
+            ),
+        }
+    }
+}
+
+impl LanguageModel {
+    /// Check the structure of the language model
+    ///
+    /// Every order but the highest needs the offset of the children of each n-gram and the end
+    /// of the last children. The children of each n-gram have to be within the level of the
+    /// next order and sorted by their label, all labels have to be in the symbol table and every n-gram of an order
+    /// above two has to link to the n-gram made of all but its first word. All violations found
+    /// are returned
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let order = self.levels.len();
+
+        if let Some(unigrams) = self.levels.first() {
+            if self.symt.len() != unigrams.ngrams.len() {
+                violations.push(Violation::UnigramCount {
+                    no_symbols: self.symt.len(),
+                    no_unigrams: unigrams.ngrams.len(),
+                });
+            }
+        }
+        for (level_idx, level) in self.levels.iter().enumerate() {
+            let no_ngrams = level.ngrams.len();
+            let no_offsets = level.offsets.len();
+            let expected_offsets = if level_idx + 1 < order {
+                no_ngrams + 1
+            } else {
+                0
+            };
+            if no_offsets != expected_offsets {
+                violations.push(Violation::OffsetCount {
+                    order: level_idx + 1,
+                    no_ngrams,
+                    no_offsets,
+                });
+            }
+            let no_suffixes = level.suffixes.len();
+            if level_idx >= 2 && no_ngrams != no_suffixes {
+                violations.push(Violation::SuffixCount {
+                    order: level_idx + 1,
+                    no_ngrams,
+                    no_suffixes,
+                });
+            }
+        }
+        if self.levels.iter().any(|level| !level.backoffs.is_empty()) {
+            for (level_idx, level) in self.levels.iter().enumerate().take(order - 1) {
+                if level.ngrams.len() != level.backoffs.len() {
+                    violations.push(Violation::BackoffCount {
+                        order: level_idx + 1,
+                        no_ngrams: level.ngrams.len(),
+                        no_backoffs: level.backoffs.len(),
+                    });
+                }
+            }
+        }
+
+        // The parent of each n-gram is the n-gram linking to it. The label of a unigram is its
+        // index
+        let mut parents: Vec<Vec<Option<usize>>> = Vec::with_capacity(order);
+        for (level_idx, level) in self.levels.iter().enumerate() {
+            let order = level_idx + 1;
+            let mut children_parents =
+                vec![None; self.levels.get(order).map_or(0, |next| next.ngrams.len())];
+            for (idx, &(label, _)) in level.ngrams.iter().enumerate() {
+                let is_valid_label = if level_idx == 0 {
+                    label as usize == idx
+                } else {
+                    (label as usize) < self.symt.len()
+                };
+                if !is_valid_label {
+                    violations.push(Violation::InvalidLabel { order, idx, label });
+                }
+                let children = self.check_children(order, idx, &mut violations);
+                for child_idx in children {
+                    children_parents[child_idx] = Some(idx);
+                }
+            }
+
+            // The suffix of an n-gram has its label and its parent is the suffix of the parent
+            // of the n-gram
+            if level_idx >= 2 {
+                let shorter_level = &self.levels[level_idx - 1];
+                for (idx, suffix_idx) in level.suffixes.iter().enumerate() {
+                    let parent_suffix = match parents[level_idx - 1].get(idx).copied().flatten() {
+                        Some(parent_idx) if level_idx == 2 => {
+                            Some(shorter_level.ngrams[parent_idx].0 as usize)
+                        }
+                        Some(parent_idx) => shorter_level.suffixes.get(parent_idx),
+                        None => None,
+                    };
+                    let is_suffix = suffix_idx < shorter_level.ngrams.len()
+                        && level.ngrams.get(idx).map(|ngram| ngram.0)
+                            == Some(shorter_level.ngrams[suffix_idx].0)
+                        && parent_suffix.is_some()
+                        && parents[level_idx - 2][suffix_idx] == parent_suffix;
+                    if !is_suffix {
+                        violations.push(Violation::InvalidSuffix {
+                            order,
+                            idx,
+                            suffix_idx,
+                        });
+                    }
+                }
+            }
+            parents.push(children_parents);
+        }
+
+        violations
+    }
+
+    /// Validate the language model and turn the violations into an error
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        let violations = self.validate();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(violations))
+        }
+    }
+
+    /// Check that the children of an n-gram are within bounds and sorted
+    /// Returns the range of the children in the level of the next order if they are within bounds
+    fn check_children(
+        &self,
+        order: usize,
+        idx: usize,
+        violations: &mut Vec<Violation>,
+    ) -> Range<usize> {
+        let level = &self.levels[order - 1];
+        let (offset, end) = match (level.offsets.get(idx), level.offsets.get(idx + 1)) {
+            (Some(offset), Some(end)) => (offset, end),
+            // The missing offsets are reported as a wrong count
+            _ => return 0..0,
+        };
+        if end < offset {
+            violations.push(Violation::OffsetsNotSorted { order, idx });
+            return 0..0;
+        }
+        let no = end - offset;
+        let children: &[Ngram] = self
+            .levels
+            .get(order)
+            .map_or(&[], |level| &level.ngrams[..]);
+        let len = children.len();
+        if offset + no > len {
+            violations.push(Violation::ChildrenOutOfBounds {
+                order,
+                idx,
+                offset,
+                no,
+                len,
+            });
+            return 0..0;
+        }
+        if children[offset..offset + no]
+            .windows(2)
+            .any(|pair| pair[0].0 >= pair[1].0)
+        {
+            violations.push(Violation::ChildrenNotSorted { order, idx });
+        }
+        offset..offset + no
+    }
+}
@@ -0,0 +1,92 @@
+use super::{builder::NgramEntry, pruning::recompute_backoffs, *};
+
+impl LanguageModel {
+    /// Restrict the vocabulary to the words of a whitelist
+    ///
+    /// Every n-gram containing a word that is not on the list is removed and the remaining
+    /// words get new labels in the order of the symbol table. Words on the list that are not
+    /// in the vocabulary are ignored. For Katz and Kneser-Ney smoothing, the unigram
+    /// probabilities are renormalized and the backoff weights recomputed, so the distributions
+    /// stay normalized over the smaller vocabulary
+    pub fn restrict_vocabulary(&mut self, words: &[&str]) -> Result<PruneReport, Error> {
+        let mut keep = vec![false; self.symt.len()];
+        for word in words {
+            if let Some(label) = self.symt.get_index_of(*word) {
+                keep[label] = true;
+            }
+        }
+        self.keep_labels(&keep)
+    }
+
+    /// Restrict the vocabulary to the words with the highest unigram probabilities
+    ///
+    /// Words with the same probability are kept in the order of the symbol table. See
+    /// `restrict_vocabulary` for how the n-grams are removed
+    pub fn limit_vocabulary(&mut self, max_size: usize) -> Result<PruneReport, Error> {
+        let unigrams = &self.levels[0].ngrams;
+        let mut labels: Vec<usize> = (0..unigrams.len()).collect();
+        labels.sort_by(|&a, &b| unigrams[b].1.total_cmp(&unigrams[a].1).then(a.cmp(&b)));
+        let mut keep = vec![false; unigrams.len()];
+        for label in labels.into_iter().take(max_size) {
+            keep[label] = true;
+        }
+        self.keep_labels(&keep)
+    }
+
+    /// Remove the words that are not kept with all n-grams containing them and renumber the
+    /// labels of the other words
+    fn keep_labels(&mut self, keep: &[bool]) -> Result<PruneReport, Error> {
+        let ngrams_before = self.no_ngrams();
+        let bytes_before = self.memory_size();
+
+        let mut new_labels: Vec<Option<Label>> = Vec::with_capacity(keep.len());
+        let mut symt = IndexSet::new();
+        for (symbol, &is_kept) in self.symt.iter().zip(keep) {
+            if is_kept {
+                new_labels.push(Some(symt.len() as Label));
+                symt.insert(symbol.clone());
+            } else {
+                new_labels.push(None);
+            }
+        }
+
+        let mut ngrams: Vec<Vec<NgramEntry>> = self
+            .to_ngrams()
+            .into_iter()
+            .map(|entries| {
+                entries
+                    .into_iter()
+                    .filter_map(|(words, log_prob, backoff)| {
+                        let words = words
+                            .iter()
+                            .map(|&label| new_labels[label as usize])
+                            .collect::<Option<Vec<Label>>>()?;
+                        Some((words, log_prob, backoff))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        if self.smoothing != Smoothing::StupidBackoff {
+            // The probability of the removed words is lost, the rest is scaled up
+            let kept_mass: f64 = ngrams[0]
+                .iter()
+                .map(|(_, log_prob, _)| (*log_prob as f64).exp())
+                .sum();
+            if kept_mass > 0.0 {
+                for (_, log_prob, _) in &mut ngrams[0] {
+                    *log_prob -= kept_mass.ln() as LogProb;
+                }
+            }
+            recompute_backoffs(&mut ngrams, |_| true);
+        }
+        *self = Self::from_ngrams(symt, ngrams, self.smoothing)?;
+
+        Ok(PruneReport {
+            ngrams_before,
+            ngrams_after: self.no_ngrams(),
+            bytes_before,
+            bytes_after: self.memory_size(),
+        })
+    }
+}
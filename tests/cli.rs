@@ -0,0 +1,100 @@
+#![cfg(feature = "cli")]
+
+use std::process::{Command, Output};
+
+mod common;
+use common::TempFile;
+
+// Run the command-line tool in the folder of the package, so the test files can be found
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_language_model"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+#[test]
+/// Convert the text files of the test folder and print the statistics of the model
+fn test_convert_and_stats() {
+    let file = TempFile::new("convert.bin");
+    let output = run(&[
+        "convert",
+        "--text",
+        "ngrams_test/symt.txt",
+        "ngrams_test/1gms.txt",
+        "ngrams_test/2gms.txt",
+        "ngrams_test/3gms.txt",
+        "-o",
+        file.path(),
+        "--compression",
+        "9",
+        "--validate",
+    ]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+
+    let output = run(&["stats", file.path()]);
+    assert!(output.status.success());
+    let stats = stdout(&output);
+    assert!(stats.contains("Format version: "));
+    assert!(stats.contains("Order: 3\n") && stats.contains("Vocabulary size: 2\n"));
+    assert!(stats.contains("1-grams: 2\n") && stats.contains("3-grams: 4\n"));
+
+    // The converted model can be queried
+    let output = run(&["predict", file.path(), "b", "b", "-k", "1"]);
+    assert!(output.status.success());
+    assert!(stdout(&output) == "a\t0.000000\n");
+}
+
+#[test]
+/// Predict the next words with and without a prefix
+fn test_predict() {
+    let output = run(&["predict", "ngrams_test/model.arpa", "b", "b"]);
+    assert!(output.status.success());
+    assert!(stdout(&output) == "a\t0.000000\nb\t-1.789388\n");
+
+    let output = run(&[
+        "predict",
+        "ngrams_test/model.arpa",
+        "--prefix",
+        "b",
+        "b",
+        "b",
+    ]);
+    assert!(output.status.success());
+    assert!(stdout(&output) == "b\t-1.789388\n");
+}
+
+#[test]
+/// Invalid arguments and errors end the tool with a failure
+fn test_errors() {
+    // The arguments can not be parsed
+    let output = run(&["convert", "-o", "model.bin"]);
+    assert!(output.status.code() == Some(2));
+    let output = run(&["frobnicate"]);
+    assert!(output.status.code() == Some(2));
+    let file = TempFile::new("errors.bin");
+    let output = run(&[
+        "convert",
+        "--arpa",
+        "ngrams_test/model.arpa",
+        "-o",
+        file.path(),
+        "--compression",
+        "10",
+    ]);
+    assert!(output.status.code() == Some(2));
+
+    // The model can not be read
+    let output = run(&["predict", "ngrams_test/missing.bin", "a"]);
+    assert!(output.status.code() == Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .starts_with("Error: I/O error"));
+}
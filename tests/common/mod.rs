@@ -0,0 +1,21 @@
+// The helpers shared by the unit tests of the library and the tests of the command-line tool
+
+// A file in the temporary folder, which is removed when it is dropped
+pub struct TempFile(String);
+
+impl TempFile {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("language_model_test_{}", name));
+        TempFile(path.to_str().unwrap().to_string())
+    }
+
+    pub fn path(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}